@@ -0,0 +1,148 @@
+// Throughput of a full reindex with different numbers of threads.
+//
+// A wiki of generated pages is written to a temporary SQLite database
+// and indexed by the wiki-search binary, once with a single thread and
+// once with each thread count given in BENCH_THREADS (by default, one
+// per cpu). The number of pages is set by BENCH_PAGES.
+//
+//     cargo bench --bench reindex
+//     BENCH_PAGES=20000 BENCH_THREADS=2,4,8 cargo bench --bench reindex
+
+use std::{
+  path::Path,
+  process::Command,
+  time::{Duration, Instant},
+};
+
+const FIXTURE: &str = include_str!("../src/wiki/fixtures/wiki.sql");
+const DEFAULT_PAGES: usize = 5000;
+
+const WORDS: [&str; 24] = [
+  "wiki", "search", "index", "river", "mountain", "language", "history",
+  "garden", "music", "travel", "kitchen", "library", "今天", "天气", "很好",
+  "我们", "一起", "去", "公园", "散步", "学习", "中文", "编程", "音乐",
+];
+
+fn main() {
+  let pages = env_or("BENCH_PAGES", DEFAULT_PAGES);
+  let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+  let mut thread_counts = vec![1];
+  match std::env::var("BENCH_THREADS") {
+    Ok(counts) => thread_counts.extend(
+      counts
+        .split(',')
+        .map(|n| n.trim().parse::<usize>().expect("invalid BENCH_THREADS")),
+    ),
+    Err(_) => thread_counts.push(cpus),
+  }
+  thread_counts.dedup();
+
+  let dir = std::env::temp_dir()
+    .join(format!("wiki-search-bench-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let db = dir.join("wiki.sqlite");
+  generate_wiki(&db, pages);
+
+  println!("reindexing {pages} pages on {cpus} cpus");
+  let mut baseline = None;
+  for threads in thread_counts {
+    let index_dir = dir.join(format!("index-{threads}"));
+    let elapsed = run_reindex(&db, &index_dir, threads);
+    let baseline = *baseline.get_or_insert(elapsed);
+    println!(
+      "{:>3} threads: {:>8.2?} {:>8.0} pages/s {:>5.2}x",
+      threads,
+      elapsed,
+      pages as f64 / elapsed.as_secs_f64(),
+      baseline.as_secs_f64() / elapsed.as_secs_f64()
+    );
+  }
+
+  let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn env_or(name: &str, default: usize) -> usize {
+  match std::env::var(name) {
+    Ok(value) => value.parse().unwrap_or_else(|_| panic!("invalid {name}")),
+    Err(_) => default,
+  }
+}
+
+fn run_reindex(db: &Path, index_dir: &Path, threads: usize) -> Duration {
+  let started = Instant::now();
+  let status = Command::new(env!("CARGO_BIN_EXE_wiki-search"))
+    .arg("--sqlite-path")
+    .arg(db)
+    .arg("--index-dir")
+    .arg(index_dir)
+    .args(["--wiki-base", "http://wiki.example/"])
+    .args(["--threads", &threads.to_string()])
+    .args(["reindex", "--full"])
+    .env("RUST_LOG", "warn")
+    .status()
+    .expect("failed to run wiki-search");
+  assert!(status.success(), "reindex failed: {status}");
+  started.elapsed()
+}
+
+// the tables of the test fixture, filled with pages of generated
+// wikitext in several sections
+fn generate_wiki(path: &Path, pages: usize) {
+  let schema = &FIXTURE[..FIXTURE.find("INSERT").unwrap()];
+  let mut conn = rusqlite::Connection::open(path).unwrap();
+  conn.execute_batch(&schema.replace("/*_*/", "")).unwrap();
+
+  let tx = conn.transaction().unwrap();
+  tx.execute("INSERT INTO actor VALUES (1, 'Bench')", [])
+    .unwrap();
+  let mut rng = 0x2545_f491_u64;
+  for id in 1..=pages as i64 {
+    let title = format!("Page_{id}");
+    let text = generate_text(&mut rng);
+    tx.execute(
+      "INSERT INTO page VALUES (?1, 0, ?2, 0, '20230101000000', ?1)",
+      rusqlite::params![id, title],
+    )
+    .unwrap();
+    tx.execute(
+      "INSERT INTO revision VALUES (?1, ?1, 1, '20230101000000')",
+      [id],
+    )
+    .unwrap();
+    tx.execute(
+      "INSERT INTO text VALUES (?1, ?2, 'utf-8')",
+      rusqlite::params![id, text],
+    )
+    .unwrap();
+    tx.execute("INSERT INTO content VALUES (?1, 'tt:' || ?1)", [id])
+      .unwrap();
+    tx.execute("INSERT INTO slots VALUES (?1, 1, ?1)", [id])
+      .unwrap();
+  }
+  tx.commit().unwrap();
+}
+
+fn generate_text(rng: &mut u64) -> String {
+  let mut text = String::new();
+  for section in 0..4 {
+    if section > 0 {
+      text.push_str(&format!("\n== Section {section} ==\n"));
+    }
+    for word in 0..120 {
+      // xorshift, to generate the same wiki on every run
+      *rng ^= *rng << 13;
+      *rng ^= *rng >> 7;
+      *rng ^= *rng << 17;
+      let word_text = WORDS[(*rng % WORDS.len() as u64) as usize];
+      match word % 15 {
+        0 => text.push_str(&format!("[[{word_text}]] ")),
+        7 => text.push_str(&format!("'''{word_text}''' ")),
+        _ => {
+          text.push_str(word_text);
+          text.push(' ');
+        }
+      }
+    }
+  }
+  text
+}
@@ -1,4 +1,6 @@
 mod cli;
+mod config;
+mod namespace;
 mod page;
 mod reindexer;
 mod search;
@@ -11,15 +13,20 @@ use cli::Cli;
 
 use crate::util::Result;
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
   tracing_subscriber::fmt().without_time().init();
 
   #[cfg(feature = "dotenv")]
   let _ = dotenv::dotenv();
 
   let cli = Cli::parse();
-  cli.run().await?;
+
+  // the runtime is built after parsing the arguments to size it
+  tokio::runtime::Builder::new_multi_thread()
+    .worker_threads(cli.threads())
+    .enable_all()
+    .build()?
+    .block_on(cli.run())?;
 
   Ok(())
 }
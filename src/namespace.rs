@@ -0,0 +1,431 @@
+// The namespaces of a wiki. The database only stores namespace
+// numbers; their names are configured in LocalSettings.php (e.g.
+// `$wgMetaNamespace`, `$wgExtraNamespaces`), so they are read from the
+// wiki's API, a dump's `<siteinfo>` or a config file, on top of the
+// built-in namespaces of MediaWiki.
+
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use crate::util::Result;
+
+pub const MAIN: i32 = 0;
+pub const CATEGORY: i32 = 14;
+
+// the namespaces every wiki has, by their canonical names
+const BUILTIN: [(i32, &str); 20] = [
+  (-2, "Media"),
+  (-1, "Special"),
+  (0, ""),
+  (1, "Talk"),
+  (2, "User"),
+  (3, "User talk"),
+  (4, "Project"),
+  (5, "Project talk"),
+  (6, "File"),
+  (7, "File talk"),
+  (8, "MediaWiki"),
+  (9, "MediaWiki talk"),
+  (10, "Template"),
+  (11, "Template talk"),
+  (12, "Help"),
+  (13, "Help talk"),
+  (14, "Category"),
+  (15, "Category talk"),
+  (828, "Module"),
+  (829, "Module talk"),
+];
+
+const BUILTIN_ALIASES: [(i32, &str); 2] = [(6, "Image"), (7, "Image talk")];
+
+static BUILTIN_NAMESPACES: Lazy<Namespaces> = Lazy::new(Namespaces::builtin);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Namespace {
+  pub id: i32,
+  /// name used in titles, empty for the main namespace and for
+  /// namespaces unknown to the registry
+  pub name: String,
+  /// other names, e.g. the canonical "Project" of a renamed namespace
+  pub aliases: Vec<String>,
+}
+
+/// All namespaces of a wiki, keyed by their number.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Namespaces(BTreeMap<i32, Namespace>);
+
+impl Namespace {
+  fn unknown(id: i32) -> Self {
+    Self {
+      id,
+      name: String::new(),
+      aliases: vec![],
+    }
+  }
+
+  // talk namespaces have odd numbers and follow their subject
+  // namespace; virtual namespaces (Special, Media) have none
+  pub fn talk_id(&self) -> Option<i32> {
+    match self.id {
+      id if id < 0 => None,
+      id => Some(id | 1),
+    }
+  }
+
+  // prefix of titles in urls, e.g. "User_talk:", or None if the name
+  // of the namespace is unknown
+  pub fn prefix(&self) -> Option<String> {
+    match self.id {
+      MAIN => Some(String::new()),
+      _ if self.name.is_empty() => None,
+      _ => Some(format!("{}:", self.name.replace(' ', "_"))),
+    }
+  }
+
+  // all names the namespace can be searched by, in both the spaced and
+  // the underscored form; the first one is the displayed name
+  pub fn search_names(&self) -> Vec<String> {
+    let mut names = vec![self.to_string()];
+    for name in self.aliases.iter().chain([&self.name]) {
+      for name in [name.clone(), name.replace(' ', "_")] {
+        if !name.is_empty() && !names.contains(&name) {
+          names.push(name);
+        }
+      }
+    }
+    names
+  }
+}
+
+impl fmt::Display for Namespace {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.id {
+      MAIN => write!(f, "Main"),
+      id if self.name.is_empty() => write!(f, "Namespace {}", id),
+      _ => write!(f, "{}", self.name),
+    }
+  }
+}
+
+// with the built-in names only; use `Namespaces::get` for the names
+// configured in the wiki
+impl From<i32> for Namespace {
+  fn from(id: i32) -> Self {
+    BUILTIN_NAMESPACES.get(id)
+  }
+}
+
+impl Default for Namespaces {
+  fn default() -> Self {
+    BUILTIN_NAMESPACES.clone()
+  }
+}
+
+impl Namespaces {
+  fn builtin() -> Self {
+    let mut namespaces = BTreeMap::new();
+    for (id, name) in BUILTIN {
+      let mut namespace = Namespace::unknown(id);
+      namespace.name = name.to_string();
+      namespaces.insert(id, namespace);
+    }
+    for (id, alias) in BUILTIN_ALIASES {
+      namespaces
+        .get_mut(&id)
+        .unwrap()
+        .aliases
+        .push(alias.to_string());
+    }
+    Self(namespaces)
+  }
+
+  pub fn get(&self, id: i32) -> Namespace {
+    match self.0.get(&id) {
+      Some(namespace) => namespace.clone(),
+      None => Namespace::unknown(id),
+    }
+  }
+
+  // Add a namespace or rename an existing one. The previous name stays
+  // valid as an alias, the same way MediaWiki still accepts the
+  // canonical names of localized namespaces. An empty name only adds
+  // the aliases.
+  pub fn define(&mut self, id: i32, name: &str, aliases: &[String]) {
+    let namespace = self.0.entry(id).or_insert_with(|| Namespace::unknown(id));
+    let name = name.trim();
+
+    if !name.is_empty() && name != namespace.name {
+      let previous = std::mem::replace(&mut namespace.name, name.to_string());
+      if !previous.is_empty() {
+        namespace.aliases.push(previous);
+      }
+    }
+
+    for alias in aliases.iter().map(|alias| alias.trim()) {
+      if !alias.is_empty() && !namespace.aliases.iter().any(|a| a == alias) {
+        namespace.aliases.push(alias.to_string());
+      }
+    }
+
+    let name = namespace.name.clone();
+    namespace.aliases.retain(|alias| *alias != name);
+  }
+
+  // Split a title like "User talk:Alice" into its namespace and the
+  // title as stored in the database ("Alice"). Namespace names are
+  // matched case-insensitively; titles without a known namespace are
+  // in the main namespace.
+  pub fn parse_title(&self, title: &str) -> (Namespace, String) {
+    let title = title.trim().replace(' ', "_");
+    let main = || (self.get(MAIN), db_title(&title));
+
+    let Some((prefix, rest)) = title.split_once(':') else {
+      return main();
+    };
+    let prefix = prefix.trim_matches('_').to_lowercase();
+    let namespace = self.0.values().find(|namespace| {
+      namespace.id != MAIN
+        && namespace
+          .search_names()
+          .iter()
+          .any(|name| name.replace(' ', "_").to_lowercase() == prefix)
+    });
+
+    match namespace {
+      Some(namespace) => (namespace.clone(), db_title(rest)),
+      None => main(),
+    }
+  }
+
+  // Load the namespaces configured in a file, either a TOML file
+  // listing the namespaces or a saved `siteinfo` response of the API
+  // (see `NamespaceConfig` and `Namespaces::fetch`).
+  pub fn load(mut self, path: &Path) -> Result<Self> {
+    let content = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+      Some("json") => self.merge_siteinfo(serde_json::from_str(&content)?),
+      _ => {
+        let config: NamespaceConfig = toml::from_str(&content)?;
+        self.merge_config(config);
+      }
+    }
+    Ok(self)
+  }
+
+  // Fetch the namespaces from the wiki's api.php, e.g.
+  // https://wiki.example/api.php
+  pub async fn fetch(mut self, api_url: &str) -> Result<Self> {
+    let query = [
+      ("action", "query"),
+      ("meta", "siteinfo"),
+      ("siprop", "namespaces|namespacealiases"),
+      ("format", "json"),
+      ("formatversion", "2"),
+    ];
+    let response = reqwest::Client::new()
+      .get(api_url)
+      .query(&query)
+      .send()
+      .await?
+      .error_for_status()?;
+    self.merge_siteinfo(response.json().await?);
+    Ok(self)
+  }
+
+  fn merge_config(&mut self, config: NamespaceConfig) {
+    for entry in config.namespace {
+      self.define(entry.id, &entry.name, &entry.aliases);
+
+      // like $wgMetaNamespaceTalk, the talk namespace is named after
+      // its subject namespace unless named explicitly
+      let subject = self.get(entry.id);
+      let Some(talk_id) = subject.talk_id().filter(|id| *id != entry.id) else {
+        continue;
+      };
+      let talk_name = entry
+        .talk
+        .unwrap_or_else(|| format!("{} talk", subject.name));
+      self.define(talk_id, &talk_name, &entry.talk_aliases);
+    }
+  }
+
+  fn merge_siteinfo(&mut self, siteinfo: SiteInfo) {
+    let SiteInfoQuery {
+      namespaces,
+      namespacealiases,
+    } = siteinfo.query;
+
+    for namespace in namespaces.into_values() {
+      self.define(namespace.id, &namespace.name, &[namespace.canonical]);
+    }
+    for alias in namespacealiases {
+      self.define(alias.id, "", &[alias.alias]);
+    }
+  }
+}
+
+// titles are stored with underscores and, as with the default
+// $wgCapitalLinks, a capitalized first letter
+fn db_title(title: &str) -> String {
+  let title = title.trim_matches('_');
+  let mut chars = title.chars();
+  match chars.next() {
+    Some(first) => first.to_uppercase().chain(chars).collect(),
+    None => String::new(),
+  }
+}
+
+/// The namespaces file, e.g.
+///
+/// ```toml
+/// [[namespace]]
+/// id = 4
+/// name = "Notebook"
+///
+/// [[namespace]]
+/// id = 100
+/// name = "Portal"
+/// aliases = ["P"]
+/// talk = "Portal discussion"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct NamespaceConfig {
+  #[serde(default)]
+  namespace: Vec<NamespaceEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamespaceEntry {
+  id: i32,
+  #[serde(default)]
+  name: String,
+  #[serde(default)]
+  aliases: Vec<String>,
+  /// name of the talk namespace, "{name} talk" by default
+  talk: Option<String>,
+  #[serde(default)]
+  talk_aliases: Vec<String>,
+}
+
+// the response of `action=query&meta=siteinfo` in format version 2
+#[derive(Debug, Deserialize)]
+struct SiteInfo {
+  query: SiteInfoQuery,
+}
+
+#[derive(Debug, Deserialize)]
+struct SiteInfoQuery {
+  namespaces: BTreeMap<String, SiteInfoNamespace>,
+  #[serde(default)]
+  namespacealiases: Vec<SiteInfoAlias>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SiteInfoNamespace {
+  id: i32,
+  name: String,
+  #[serde(default)]
+  canonical: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SiteInfoAlias {
+  id: i32,
+  alias: String,
+}
+
+#[cfg(test)]
+mod test {
+  use super::{NamespaceConfig, Namespaces, SiteInfo};
+
+  #[test]
+  fn test_builtin_namespaces() {
+    let namespaces = Namespaces::default();
+
+    let project = namespaces.get(4);
+    assert_eq!(project.name, "Project");
+    assert_eq!(project.prefix().as_deref(), Some("Project:"));
+    assert_eq!(namespaces.get(0).prefix().as_deref(), Some(""));
+    assert_eq!(namespaces.get(3).prefix().as_deref(), Some("User_talk:"));
+    assert_eq!(namespaces.get(6).aliases, ["Image"]);
+
+    assert_eq!(project.talk_id(), Some(5));
+    assert_eq!(namespaces.get(5).name, "Project talk");
+    assert_eq!(namespaces.get(-1).talk_id(), None);
+
+    let unknown = namespaces.get(100);
+    assert_eq!(unknown.prefix(), None);
+    assert_eq!(unknown.to_string(), "Namespace 100");
+  }
+
+  #[test]
+  fn test_parse_title() {
+    let namespaces = Namespaces::default();
+    let parse = |title| {
+      let (namespace, title) = namespaces.parse_title(title);
+      (namespace.id, title)
+    };
+
+    assert_eq!(parse("Rust (language)"), (0, "Rust_(language)".into()));
+    assert_eq!(parse("user talk:alice"), (3, "Alice".into()));
+    assert_eq!(parse("Image:Cat.jpg"), (6, "Cat.jpg".into()));
+    assert_eq!(parse("Rust: a language"), (0, "Rust:_a_language".into()));
+  }
+
+  #[test]
+  fn test_namespace_config() {
+    let config: NamespaceConfig = toml::from_str(
+      r#"
+        [[namespace]]
+        id = 4
+        name = "Notebook"
+
+        [[namespace]]
+        id = 100
+        name = "Portal"
+        aliases = ["P"]
+      "#,
+    )
+    .unwrap();
+    let mut namespaces = Namespaces::default();
+    namespaces.merge_config(config);
+
+    let project = namespaces.get(4);
+    assert_eq!(project.name, "Notebook");
+    assert_eq!(project.aliases, ["Project"]);
+    // the talk namespace follows its subject namespace
+    let project_talk = namespaces.get(5);
+    assert_eq!(project_talk.name, "Notebook talk");
+    assert_eq!(project_talk.aliases, ["Project talk"]);
+
+    let portal = namespaces.get(100);
+    assert_eq!(portal.prefix().as_deref(), Some("Portal:"));
+    assert_eq!(portal.search_names(), ["Portal", "P"]);
+    let talk = namespaces.get(101);
+    assert_eq!(talk.name, "Portal talk");
+    assert_eq!(talk.search_names(), ["Portal talk", "Portal_talk"]);
+  }
+
+  #[test]
+  fn test_siteinfo() {
+    let siteinfo: SiteInfo = serde_json::from_str(
+      r#"{"batchcomplete": true, "query": {
+        "namespaces": {
+          "0": {"id": 0, "case": "first-letter", "name": "", "canonical": ""},
+          "4": {"id": 4, "name": "Mein Wiki", "canonical": "Project"},
+          "100": {"id": 100, "name": "Tagebuch", "canonical": "Diary"}
+        },
+        "namespacealiases": [{"id": 100, "alias": "TB"}]
+      }}"#,
+    )
+    .unwrap();
+    let mut namespaces = Namespaces::default();
+    namespaces.merge_siteinfo(siteinfo);
+
+    assert_eq!(namespaces.get(4).prefix().as_deref(), Some("Mein_Wiki:"));
+    assert_eq!(namespaces.get(100).aliases, ["Diary", "TB"]);
+    assert_eq!(namespaces.get(0).to_string(), "Main");
+  }
+}
@@ -1,110 +1,107 @@
 use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
 
-use crate::util::{Date, DateTime, Error};
-
-#[derive(Clone, Copy, Debug, derive_more::Display)]
-pub enum Namespace {
-  Main,
-  User,
-  File,
-  Template,
-  Category,
-  Special,
-  MediaWiki,
-  Help,
-  Module,
-  MainTalk,
-  UserTalk,
-  FileTalk,
-  TemplateTalk,
-  CategoryTalk,
-  MediaWikiTalk,
-  HelpTalk,
-  ModuleTalk,
-  Media,
-  Other(i32),
-}
+use crate::{
+  namespace::Namespace,
+  util::{Date, DateTime, Error},
+};
 
 #[derive(Clone, Debug, sqlx::FromRow)]
 pub struct Page {
   pub id: i64,
   pub title: String,
+  /// decoded from the stored text by the wiki reader
+  #[sqlx(skip)]
   pub text: String,
   #[sqlx(rename = "title", try_from = "String")]
   pub title_date: TitleDate,
   #[sqlx(try_from = "String")]
   pub updated: WikiTimestamp,
+  /// timestamp of the first revision
+  #[sqlx(try_from = "String")]
+  pub created: WikiTimestamp,
+  /// author of the first revision
+  pub creator: String,
+  /// author of the latest revision
+  pub last_editor: String,
+  pub revision_count: i64,
   #[sqlx(try_from = "i32")]
   pub namespace: Namespace,
   #[sqlx(default)]
   pub url: String,
   #[sqlx(try_from = "String")]
   pub categories: Categories,
+  /// titles of the pages redirecting to this page
+  #[sqlx(try_from = "String")]
+  pub aliases: Categories,
+  #[sqlx(skip)]
+  pub sections: Vec<Section>,
+}
+
+/// A part of a page delimited by headings.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Section {
+  /// headings enclosing the section, empty for the lead section
+  pub headings: Vec<String>,
+  /// anchor of the heading on the page
+  pub anchor: String,
+  pub text: String,
+}
+
+/// A past or current revision of a page, indexed in history mode.
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Revision {
+  pub id: i64,
+  pub page_id: i64,
+  pub title: String,
+  #[sqlx(skip)]
+  pub text: String,
+  #[sqlx(rename = "title", try_from = "String")]
+  pub title_date: TitleDate,
+  #[sqlx(try_from = "i32")]
+  pub namespace: Namespace,
+  #[sqlx(try_from = "String")]
+  pub timestamp: WikiTimestamp,
+  /// timestamp of the next revision, None for the latest revision
+  #[sqlx(skip)]
+  pub valid_until: Option<WikiTimestamp>,
+  pub actor: String,
+  #[sqlx(default)]
+  pub url: String,
+}
+
+impl Revision {
+  pub fn fill_url(&mut self, base: &str) {
+    self.url = match page_url(base, &self.namespace, &self.title) {
+      Some(page_url) => format!("{}?oldid={}", page_url, self.id),
+      None => format!("{}Special:Redirect/revision/{}", base, self.id),
+    };
+  }
+}
+
+// None if the name of the namespace is unknown, in which case the page
+// can only be linked to by its id
+fn page_url(base: &str, namespace: &Namespace, title: &str) -> Option<String> {
+  let prefix = namespace.prefix()?;
+  Some(format!("{}{}{}", base, prefix, title))
 }
 
 impl Page {
   pub fn to_url(&self, base: &str) -> String {
-    format!("{}{}{}", base, self.namespace.to_prefix(), self.title)
+    page_url(base, &self.namespace, &self.title)
+      .unwrap_or_else(|| format!("{}Special:Redirect/page/{}", base, self.id))
   }
 
   pub fn fill_url(&mut self, base: &str) {
     self.url = self.to_url(base);
   }
-}
 
-impl From<i32> for Namespace {
-  fn from(value: i32) -> Self {
-    match value {
-      0 => Namespace::Main,
-      2 => Namespace::User,
-      4 => Namespace::Template,
-      6 => Namespace::File,
-      8 => Namespace::MediaWiki,
-      10 => Namespace::Template,
-      12 => Namespace::Help,
-      14 => Namespace::Category,
-      1 => Namespace::MainTalk,
-      3 => Namespace::UserTalk,
-      5 => Namespace::TemplateTalk,
-      7 => Namespace::FileTalk,
-      9 => Namespace::MediaWikiTalk,
-      11 => Namespace::TemplateTalk,
-      13 => Namespace::HelpTalk,
-      15 => Namespace::CategoryTalk,
-      828 => Namespace::Module,
-      829 => Namespace::ModuleTalk,
-      -1 => Namespace::Special,
-      -2 => Namespace::Media,
-      i => Namespace::Other(i),
+  pub fn section_url(&self, section: &Section) -> String {
+    if section.anchor.is_empty() {
+      return self.url.clone();
     }
-  }
-}
-
-impl Namespace {
-  pub fn to_prefix(self) -> &'static str {
-    use Namespace::*;
 
-    match self {
-      Main => "",
-      User => "User:",
-      File => "File:",
-      Template => "Template:",
-      Category => "Category:",
-      Special => "Special:",
-      MediaWiki => "MediaWiki:",
-      Help => "Help:",
-      Module => "Module:",
-      MainTalk => "Talk:",
-      UserTalk => "User_talk:",
-      FileTalk => "File_talk:",
-      TemplateTalk => "Template_talk:",
-      CategoryTalk => "Category_talk:",
-      MediaWikiTalk => "MediaWiki_talk:",
-      HelpTalk => "Help_talk:",
-      ModuleTalk => "Module_talk:",
-      Media => "Media:",
-      Other(_) => "Unknown",
-    }
+    format!("{}#{}", self.url, section.anchor)
   }
 }
 
@@ -186,6 +183,13 @@ impl TitleDate {
 #[derive(
   Clone,
   Debug,
+  Default,
+  PartialEq,
+  Eq,
+  PartialOrd,
+  Ord,
+  Serialize,
+  Deserialize,
   derive_more::From,
   derive_more::Into,
   derive_more::AsRef,
@@ -193,13 +197,15 @@ impl TitleDate {
 )]
 pub struct WikiTimestamp(pub DateTime);
 
+const WIKI_TIMESTAMP_FORMAT: &str = "%Y%m%d%H%M%S";
+
 impl TryFrom<String> for WikiTimestamp {
   type Error = Error;
 
   fn try_from(value: String) -> Result<Self, Self::Error> {
-    const FORMAT: &str = "%Y%m%d%H%M%S";
-    let date_time = NaiveDateTime::parse_from_str(&value, FORMAT)
-      .map_err(|_e| Error::InvalidDate(value))?;
+    let date_time =
+      NaiveDateTime::parse_from_str(&value, WIKI_TIMESTAMP_FORMAT)
+        .map_err(|_e| Error::InvalidDate(value))?;
     Ok(WikiTimestamp(date_time.and_utc()))
   }
 }
@@ -208,6 +214,11 @@ impl WikiTimestamp {
   pub fn timestamp(&self) -> i64 {
     self.0.timestamp()
   }
+
+  // format in the way MediaWiki stores timestamps in the database
+  pub fn to_wiki_format(&self) -> String {
+    self.0.format(WIKI_TIMESTAMP_FORMAT).to_string()
+  }
 }
 
 #[derive(Clone, Debug, derive_more::From)]
@@ -216,9 +227,12 @@ pub enum Categories {
   Split(Vec<String>),
 }
 
+// Also used for other lists concatenated by the database, like the
+// aliases of a page.
 impl Categories {
   pub fn iter(&self) -> Box<dyn Iterator<Item = &str> + '_> {
     match self {
+      Categories::Raw(s) if s.is_empty() => Box::new(std::iter::empty()),
       Categories::Raw(s) => Box::new(s.split("<|||>")),
       Categories::Split(v) => Box::new(v.iter().map(|s| s.as_str())),
     }
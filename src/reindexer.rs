@@ -1,66 +1,378 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+  collections::HashSet,
+  path::PathBuf,
+  sync::Arc,
+  time::{Duration, Instant},
+};
 
-use tokio::sync::{Mutex, RwLock};
+use futures_util::{stream::BoxStream, TryStreamExt};
+use serde::{Deserialize, Serialize};
 use tracing::{debug, info, warn};
 
-use crate::{search::Search, util::Result, wiki::Wiki};
+use self::watch::DatabaseWatcher;
+
+mod watch;
+
+use crate::{
+  page::Revision,
+  search::{Search, SearchWriter},
+  util::{in_background, Result},
+  wiki::{Dump, Fingerprint, StreamedPage, Wiki},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReindexMode {
+  /// only re-index pages changed since the last reindex
+  #[default]
+  Incremental,
+  /// rebuild the whole index from scratch
+  Full,
+}
+
+impl ReindexMode {
+  pub fn full_if(full: bool) -> Self {
+    if full {
+      ReindexMode::Full
+    } else {
+      ReindexMode::Incremental
+    }
+  }
+}
+
+// Bring the index up to date with the wiki. Returns the number of
+// pages (re-)indexed or removed, or None if the index is already up
+// to date. Pages are streamed into the index writer as they are read,
+// and searches keep seeing the last commit until the reindex is done.
+// A full reindex builds a new generation of the index, which only goes
+// live once it is complete and passed its checks.
+pub async fn reindex(
+  wiki: &Wiki,
+  search: &Search,
+  mode: ReindexMode,
+) -> Result<Option<usize>> {
+  let started = Instant::now();
+  let fingerprint = wiki.fingerprint().await?;
+
+  let last = match mode {
+    ReindexMode::Incremental => search.fingerprint(),
+    ReindexMode::Full => None,
+  };
+  if last.is_some() && !search.requires_reindex(&fingerprint) {
+    return Ok(None);
+  }
+
+  let Some(last) = last else {
+    let writer = search.new_generation().await?;
+    let (indexed, _) = index_pages(wiki.stream_pages(None), &writer).await?;
+    index_revisions(wiki.stream_revisions(None), &writer).await?;
+    search.publish(writer, fingerprint, started, indexed.len())?;
+    return Ok(Some(indexed.len()));
+  };
+
+  let writer = search.writer().await?;
+  let (changed, skipped) =
+    index_pages(wiki.stream_pages(Some(&last)), &writer).await?;
+  index_revisions(wiki.stream_revisions(Some(&changed[..])), &writer).await?;
+
+  let existing: HashSet<i64> =
+    wiki.list_page_ids().await?.into_iter().collect();
+  let deleted: Vec<i64> = search
+    .indexed_page_ids()?
+    .into_iter()
+    .filter(|id| !existing.contains(id))
+    .collect();
+  for id in &deleted {
+    writer.remove_page(*id);
+  }
+
+  search.commit(writer, fingerprint, started)?;
+  Ok(Some(changed.len() + skipped.len() + deleted.len()))
+}
+
+/// A change of a single page, as notified by the wiki.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageEvent {
+  /// the page was created, edited or restored
+  #[default]
+  Edit,
+  /// the page was renamed, keeping its id
+  Move,
+  Delete,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageUpdate {
+  Updated,
+  Removed,
+}
+
+// Update or remove the documents of a single page right after it
+// changed, without looking at other pages. Waits for a running
+// reindex to be committed first. Pages that are gone, turned into
+// redirects or became empty are removed.
+pub async fn reindex_page(
+  wiki: &Wiki,
+  search: &Search,
+  id: i64,
+  event: PageEvent,
+) -> Result<PageUpdate> {
+  let page = match event {
+    PageEvent::Edit | PageEvent::Move => wiki.get_page(id).await?,
+    PageEvent::Delete => None,
+  };
+
+  let writer = search.writer().await?;
+  writer.remove_page(id);
+  let update = match page {
+    Some(page) => {
+      writer.add_docs(writer.doc_builder().page_docs(page)?)?;
+      index_revisions(wiki.stream_revisions(Some(&[id][..])), &writer).await?;
+      PageUpdate::Updated
+    }
+    None => PageUpdate::Removed,
+  };
+
+  search.commit_pages(writer)?;
+  info!("reindexed page {} after {:?}: {:?}", id, event, update);
+  Ok(update)
+}
+
+// Rebuild the index from an XML dump. No fingerprint of a live wiki
+// is stored, so the next incremental reindex against the wiki's
+// database lists all pages again. Returns the number of pages indexed.
+pub async fn reindex_dump(dump: Dump, search: &Search) -> Result<usize> {
+  let started = Instant::now();
+  let dump = dump.scan().await?;
+
+  let writer = search.new_generation().await?;
+  let (pages, revisions) = dump.stream();
+  let ((indexed, _), ()) = futures_util::try_join!(
+    index_pages(pages, &writer),
+    index_revisions(revisions, &writer)
+  )?;
+
+  search.publish(writer, Fingerprint::default(), started, indexed.len())?;
+  Ok(indexed.len())
+}
+
+// Add the streamed pages to the index, replacing their previous
+// documents, and remove those of the skipped pages. The documents are
+// built on as many threads as the writer has, keeping the order of the
+// pages. Returns the ids of the indexed pages and of the skipped ones.
+async fn index_pages(
+  pages: BoxStream<'_, Result<StreamedPage>>,
+  writer: &SearchWriter,
+) -> Result<(Vec<i64>, Vec<i64>)> {
+  let mut progress = Progress::new("pages");
+  let (mut ids, mut skipped) = (vec![], vec![]);
+
+  let builder = writer.doc_builder();
+  let mut built = pages
+    .map_ok(|page| {
+      let builder = builder.clone();
+      async move {
+        let (id, page) = match page {
+          StreamedPage::Page(page) => (page.id, Some(page)),
+          StreamedPage::Skipped(id) => (id, None),
+        };
+        let docs =
+          in_background(page, move |page| Ok(Some(builder.page_docs(page)?)));
+        Ok((id, docs.await?))
+      }
+    })
+    .try_buffered(writer.threads());
+
+  while let Some((id, docs)) = built.try_next().await? {
+    progress.record(docs.is_some());
+    writer.remove_page(id);
+    match docs {
+      Some(docs) => {
+        ids.push(id);
+        writer.add_docs(docs)?;
+      }
+      None => skipped.push(id),
+    }
+  }
+
+  progress.report();
+  Ok((ids, skipped))
+}
+
+// the previous documents of the pages must be removed already
+async fn index_revisions(
+  revisions: BoxStream<'_, Result<Option<Revision>>>,
+  writer: &SearchWriter,
+) -> Result<()> {
+  let mut progress = Progress::new("revisions");
+
+  let builder = writer.doc_builder();
+  let mut built = revisions
+    .map_ok(|revision| {
+      let builder = builder.clone();
+      in_background(revision, move |revision| {
+        Ok(Some(builder.revision_doc(revision)))
+      })
+    })
+    .try_buffered(writer.threads());
+
+  while let Some(doc) = built.try_next().await? {
+    progress.record(doc.is_some());
+    if let Some(doc) = doc {
+      writer.add_docs(vec![doc])?;
+    }
+  }
+
+  if progress.seen > 0 {
+    progress.report();
+  }
+  Ok(())
+}
+
+// how often a running reindex logs its progress
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Counts of the pages or revisions seen and indexed by a running
+/// reindex. Pages are seen but not indexed if they are empty or their
+/// text can't be decoded.
+struct Progress {
+  what: &'static str,
+  seen: usize,
+  indexed: usize,
+  last_report: Instant,
+}
+
+impl Progress {
+  fn new(what: &'static str) -> Self {
+    Self {
+      what,
+      seen: 0,
+      indexed: 0,
+      last_report: Instant::now(),
+    }
+  }
+
+  fn record(&mut self, indexed: bool) {
+    self.seen += 1;
+    if indexed {
+      self.indexed += 1;
+    }
+    if self.last_report.elapsed() >= PROGRESS_INTERVAL {
+      self.report();
+    }
+  }
+
+  fn report(&mut self) {
+    info!(
+      "reindexing: {} {} seen, {} indexed",
+      self.seen, self.what, self.indexed
+    );
+    self.last_report = Instant::now();
+  }
+}
 
 pub struct Reindexer {
-  search: Arc<RwLock<Search>>,
-  wiki: Arc<Mutex<Wiki>>,
+  search: Arc<Search>,
+  wiki: Arc<Wiki>,
   reindex_interval: Duration,
+  /// SQLite database to watch for changes, and how often to poll it
+  /// if it can't be watched
+  watch: Option<(PathBuf, Duration)>,
 }
 
 // reindex every hour
 const DEFAULT_REINDEX_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
+// retry rebuilding an outdated index sooner, as it may not be
+// searchable until then
+const OUTDATED_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
 impl Reindexer {
-  pub fn new(search: Arc<RwLock<Search>>, wiki: Arc<Mutex<Wiki>>) -> Reindexer {
+  pub fn new(search: Arc<Search>, wiki: Arc<Wiki>) -> Reindexer {
     Self {
       search,
       wiki,
       reindex_interval: DEFAULT_REINDEX_INTERVAL,
+      watch: None,
     }
   }
 
-  #[allow(unused)]
   pub fn with_interval(mut self, interval: Duration) -> Self {
     self.reindex_interval = interval;
     self
   }
 
+  // Reindex soon after the SQLite database at the path changes, in
+  // addition to reindexing at the interval.
+  pub fn with_watch(mut self, database: PathBuf, poll: Duration) -> Self {
+    self.watch = Some((database, poll));
+    self
+  }
+
   async fn run(self) {
+    let mut watcher =
+      self.watch.as_ref().and_then(
+        |(database, poll)| match DatabaseWatcher::new(database, *poll) {
+          Ok(watcher) => Some(watcher),
+          Err(e) => {
+            warn!("cannot watch {}: {}", database.display(), e);
+            None
+          }
+        },
+      );
+
+    if watcher.is_some() {
+      info!("Reindexer started, watching the database for changes");
+    }
     info!("Reindexer started at interval {:?}", self.reindex_interval);
 
     loop {
+      let mut interval = self.reindex_interval;
       match self.reindex().await {
         Ok(_) => {}
         Err(e) => {
           warn!("scheduled reindex failed: {}", e);
+          if self.search.outdated().is_some() {
+            interval = interval.min(OUTDATED_RETRY_INTERVAL);
+          }
         }
       }
-      tokio::time::sleep(self.reindex_interval).await;
+
+      match &mut watcher {
+        Some(watcher) => watcher.changed(interval).await,
+        None => tokio::time::sleep(interval).await,
+      }
     }
   }
 
   async fn reindex(&self) -> Result<()> {
-    let mut wiki = self.wiki.lock().await;
-    let mut search = self.search.write().await;
-    let revision = wiki.latest_revision().await?;
-    if !search.requires_reindex(revision) {
+    let search = &self.search;
+    let mode = ReindexMode::Incremental;
+    let Some(updated) = reindex(&self.wiki, search, mode).await? else {
       debug!("no reindex required");
       return Ok(());
-    }
-
-    let pages = wiki.list_pages().await?;
-    search.reindex_pages(pages, revision)?;
+    };
 
     let page_count = search.page_count()?;
-    info!("reindex successful, indexed {} pages", page_count);
+    info!(
+      "reindex successful, updated {} pages, indexed {} pages",
+      updated, page_count
+    );
     Ok(())
   }
 
   pub fn start(self) {
     tokio::task::spawn(self.run());
   }
+
+  // Reindex once in the background, e.g. to rebuild an outdated index
+  // when the server doesn't reindex on a schedule.
+  pub fn start_once(self) {
+    tokio::task::spawn(async move {
+      if let Err(e) = self.reindex().await {
+        warn!("reindex failed: {}", e);
+      }
+    });
+  }
 }
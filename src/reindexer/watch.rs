@@ -0,0 +1,226 @@
+// Watching a SQLite database for changes. MediaWiki's writes land in
+// the database file, or in its write-ahead log while the wiki runs in
+// WAL mode, so both files are watched. Their directory is watched
+// rather than the files themselves, as the log is created and removed
+// by SQLite as it goes. Where inotify (or its counterpart on other
+// systems) isn't available, the files are polled for changes instead.
+
+use std::{
+  ffi::OsString,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use notify::{
+  event::ModifyKind, Config, Event, EventKind, PollWatcher, RecommendedWatcher,
+  RecursiveMode, Watcher,
+};
+use tokio::{sync::mpsc, time::Instant};
+use tracing::{debug, warn};
+
+use crate::util::{Error, Result};
+
+// a change is only reported once the files stay untouched this long,
+// as saving a page takes several writes
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+// but no later than this after the first write, for wikis that are
+// never idle that long (e.g. with bots or a busy job queue)
+const MAX_DEBOUNCE: Duration = Duration::from_secs(30);
+
+pub struct DatabaseWatcher {
+  // stops watching when dropped
+  _watcher: Box<dyn Watcher + Send>,
+  changes: mpsc::UnboundedReceiver<()>,
+}
+
+impl DatabaseWatcher {
+  // Watch the database file, polling it every `poll_interval` if the
+  // system can't notify about changes.
+  pub fn new(database: &Path, poll_interval: Duration) -> Result<Self> {
+    let database = database.canonicalize()?;
+    let (dir, names) = watched_names(&database)?;
+
+    let (sender, changes) = mpsc::unbounded_channel();
+    let handler = move |event: notify::Result<Event>| {
+      let Ok(event) = event else { return };
+      let watched = |path: &PathBuf| {
+        let name = path.file_name();
+        names
+          .iter()
+          .any(|watched| Some(watched.as_os_str()) == name)
+      };
+      if is_change(&event.kind) && event.paths.iter().any(watched) {
+        let _ = sender.send(());
+      }
+    };
+
+    let watcher: Box<dyn Watcher + Send> =
+      match RecommendedWatcher::new(handler.clone(), Config::default())
+        .and_then(|watcher| watch(watcher, &dir))
+      {
+        Ok(watcher) => Box::new(watcher),
+        Err(e) => {
+          warn!("cannot watch {}: {}, polling it instead", dir.display(), e);
+          let config = Config::default().with_poll_interval(poll_interval);
+          Box::new(watch(PollWatcher::new(handler, config)?, &dir)?)
+        }
+      };
+
+    Ok(Self {
+      _watcher: watcher,
+      changes,
+    })
+  }
+
+  // Wait until the database changed and then settled, or kept
+  // changing for `MAX_DEBOUNCE`, or until the timeout passed without a
+  // change.
+  pub async fn changed(&mut self, timeout: Duration) {
+    match tokio::time::timeout(timeout, self.changes.recv()).await {
+      Ok(Some(())) => {}
+      Ok(None) => {
+        warn!("the database is no longer watched, reindexing at intervals");
+        return tokio::time::sleep(timeout).await;
+      }
+      Err(_) => return,
+    }
+
+    let deadline = Instant::now() + MAX_DEBOUNCE;
+    let mut writes = 1;
+    loop {
+      let settled = deadline.min(Instant::now() + DEBOUNCE);
+      match tokio::time::timeout_at(settled, self.changes.recv()).await {
+        Ok(Some(())) => writes += 1,
+        _ => break,
+      }
+    }
+    debug!("database changed ({} writes)", writes);
+  }
+}
+
+fn watch<W: Watcher>(mut watcher: W, dir: &Path) -> notify::Result<W> {
+  watcher.watch(dir, RecursiveMode::NonRecursive)?;
+  Ok(watcher)
+}
+
+// the directory of the database, and the names of the database file
+// and its write-ahead log in it
+fn watched_names(database: &Path) -> Result<(PathBuf, Vec<OsString>)> {
+  let (Some(dir), Some(name)) = (database.parent(), database.file_name())
+  else {
+    return Err(Error::Generic(format!(
+      "not a database file: {}",
+      database.display()
+    )));
+  };
+
+  let mut wal = name.to_os_string();
+  wal.push("-wal");
+  Ok((dir.to_path_buf(), vec![name.to_os_string(), wal]))
+}
+
+// Reads and attribute changes are not changes of the content. This
+// also skips the reads of the reindex itself.
+fn is_change(kind: &EventKind) -> bool {
+  match kind {
+    EventKind::Create(_) | EventKind::Remove(_) | EventKind::Any => true,
+    EventKind::Modify(ModifyKind::Metadata(_)) => false,
+    EventKind::Modify(_) => true,
+    EventKind::Access(_) | EventKind::Other => false,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::{ffi::OsString, path::Path};
+
+  use notify::{
+    event::{AccessKind, CreateKind, DataChange, MetadataKind, ModifyKind},
+    EventKind, NullWatcher,
+  };
+  use tokio::{
+    sync::mpsc,
+    time::{Duration, Instant},
+  };
+
+  use super::{
+    is_change, watched_names, DatabaseWatcher, DEBOUNCE, MAX_DEBOUNCE,
+  };
+
+  #[test]
+  fn test_watched_names() {
+    let (dir, names) =
+      watched_names(Path::new("/var/lib/wiki/my_wiki.sqlite")).unwrap();
+    assert_eq!(dir, Path::new("/var/lib/wiki"));
+    assert_eq!(
+      names,
+      [
+        OsString::from("my_wiki.sqlite"),
+        OsString::from("my_wiki.sqlite-wal")
+      ]
+    );
+    assert!(watched_names(Path::new("/")).is_err());
+  }
+
+  #[test]
+  fn test_is_change() {
+    assert!(is_change(&EventKind::Create(CreateKind::File)));
+    assert!(is_change(&EventKind::Modify(ModifyKind::Data(
+      DataChange::Content
+    ))));
+    assert!(!is_change(&EventKind::Modify(ModifyKind::Metadata(
+      MetadataKind::Permissions
+    ))));
+    assert!(!is_change(&EventKind::Access(AccessKind::Read)));
+  }
+
+  // a watcher fed by the returned sender instead of the files
+  fn watcher() -> (mpsc::UnboundedSender<()>, DatabaseWatcher) {
+    let (sender, changes) = mpsc::unbounded_channel();
+    let watcher = DatabaseWatcher {
+      _watcher: Box::new(NullWatcher),
+      changes,
+    };
+    (sender, watcher)
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_changed() {
+    let timeout = Duration::from_secs(60);
+    let (sender, mut watcher) = watcher();
+
+    // no change until the timeout
+    let started = Instant::now();
+    watcher.changed(timeout).await;
+    assert_eq!(started.elapsed(), timeout);
+
+    // a few writes, reported once they settled
+    let started = Instant::now();
+    for _ in 0..3 {
+      sender.send(()).unwrap();
+    }
+    watcher.changed(timeout).await;
+    assert_eq!(started.elapsed(), DEBOUNCE);
+
+    // writes that never settle, reported after the longest debounce
+    let writer = sender.clone();
+    let writes = tokio::spawn(async move {
+      while writer.send(()).is_ok() {
+        tokio::time::sleep(DEBOUNCE / 2).await;
+      }
+    });
+    let started = Instant::now();
+    watcher.changed(timeout).await;
+    assert_eq!(started.elapsed(), MAX_DEBOUNCE);
+    writes.abort();
+    let _ = writes.await;
+
+    // without a watcher, only the timeout is waited for
+    drop(sender);
+    while watcher.changes.try_recv().is_ok() {}
+    let started = Instant::now();
+    watcher.changed(timeout).await;
+    assert_eq!(started.elapsed(), timeout);
+  }
+}
@@ -1,40 +1,188 @@
 use std::{
+  borrow::Cow,
+  collections::HashSet,
   ops::{Bound, Range},
-  path::Path,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex, RwLock},
+  time::{Duration, Instant},
 };
 
 use clap::Args;
-use serde::Deserialize;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
 use tantivy::{
-  collector::MultiCollector,
+  collector::{Count, TopDocs},
   directory::MmapDirectory,
-  query::{AllQuery, Query},
-  schema::{Field, Schema},
-  tokenizer::TextAnalyzer,
-  DateTime, DocAddress, Document, Index, IndexWriter, Order, Searcher, Snippet,
-  SnippetGenerator,
+  query::{AllQuery, Query, TermQuery},
+  schema::{Field, IndexRecordOption, Schema},
+  DateTime, DocAddress, Document, Index, IndexSettings, IndexWriter, Score,
+  Searcher, Snippet, SnippetGenerator, Term,
 };
-use tantivy_jieba::JiebaTokenizer;
+use tokio::sync::OwnedMutexGuard;
+use tracing::{info, warn};
 
-use crate::{page::Page, util::Result};
+use self::{
+  collector::{PageOrder, TopPages},
+  language::Language,
+};
+
+mod analyzer;
+mod collector;
+mod filter;
+mod generation;
+mod language;
+mod pinyin;
+mod synonyms;
+
+pub use self::{analyzer::AnalysisConfig, synonyms::Synonyms};
 
+use crate::{
+  page::{Page, Revision, Section},
+  util::{self, Date, DateTime as UtcDateTime, Error, Result},
+  wiki::Fingerprint,
+};
+
+#[derive(Clone)]
 pub struct Fields {
   id: Field,
   title: Field,
   text: Field,
   title_date: Field,
   updated: Field,
+  created: Field,
+  creator: Field,
+  last_editor: Field,
+  revision_count: Field,
   namespace: Field,
   url: Field,
   category: Field,
+  section: Field,
+  section_no: Field,
+  alias: Field,
+  kind: Field,
+  revision: Field,
+  valid_from: Field,
+  valid_until: Field,
+  actor: Field,
+  language: Field,
+  /// the pinyin of the CJK words of the title and text, see `pinyin`
+  pinyin: Field,
+  localized: Vec<LocalizedFields>,
 }
 
+/// The title and text fields of a language with its own analyzer, see
+/// `language`.
+#[derive(Clone)]
+struct LocalizedFields {
+  code: &'static str,
+  title: Field,
+  text: Field,
+}
+
+// values of the `kind` field
+const KIND_CURRENT: &str = "current";
+const KIND_HISTORY: &str = "history";
+
+const ALIAS_BOOST: f32 = 2.0;
+
+// the latest revision of a page stays valid until this date (2100-01-01)
+const OPEN_END_TIMESTAMP: i64 = 4_102_444_800;
+
+// Version of the schema and the analyzers, saved with each commit.
+// Bump it whenever `build_schema` or the built-in tokenizers change,
+// so that existing indexes are rebuilt instead of mixing analyzers.
+// Changes to the configured analyzers are told by their digest.
+const SCHEMA_VERSION: u32 = 5;
+
+// memory of the index writer, shared by its threads
+const WRITER_MEMORY_BUDGET: usize = 128_000_000;
+// tantivy requires at least 15MB of the budget per indexing thread
+const MAX_WRITER_THREADS: usize = 8;
+
 pub struct Search {
-  #[allow(unused)]
   schema: Schema,
   fields: Fields,
+  analysis: AnalysisConfig,
+  /// synonyms expanding queries
+  synonyms: Synonyms,
+  index_dir: PathBuf,
+  /// the generation searched and updated in place, replaced by full
+  /// rebuilds
+  live: RwLock<Arc<Generation>>,
+  /// held by the open writer, as an index can only have one, and while
+  /// a new generation is built
+  writer_lock: Arc<tokio::sync::Mutex<()>>,
+  /// number of threads building and indexing documents
+  threads: usize,
+  /// whether past revisions are indexed
+  history: bool,
+}
+
+/// An open writer of the index. Its changes become visible to searches
+/// once committed with `Search::commit`, and are discarded if it is
+/// dropped before.
+pub struct SearchWriter {
+  writer: IndexWriter,
+  generation: Arc<Generation>,
+  builder: DocBuilder,
+  threads: usize,
+  _lock: OwnedMutexGuard<()>,
+}
+
+/// An index on disk, see `generation`.
+struct Generation {
+  /// None for an index built before generations
+  name: Option<String>,
   index: Index,
-  revision: u32,
+  state: Mutex<Option<IndexState>>,
+  /// why the index needs a rebuild, if it was built by another version
+  outdated: Option<String>,
+}
+
+/// Turns pages and revisions into documents of the index. It is cheap
+/// to clone, so that documents can be built on other threads.
+#[derive(Clone)]
+pub struct DocBuilder {
+  fields: Fields,
+}
+
+/// Information about the last reindex, persisted as the payload of
+/// the index commit so that it survives restarts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexState {
+  pub fingerprint: Fingerprint,
+  pub reindexed_at: UtcDateTime,
+  pub reindex_duration: Duration,
+  /// the `SCHEMA_VERSION` the index was built with, 0 if unknown
+  #[serde(default)]
+  pub schema_version: u32,
+  /// the `AnalysisConfig::digest` of the analyzers the index was built
+  /// with, None for the built-in ones
+  #[serde(default)]
+  pub analyzers: Option<String>,
+  /// whether past revisions were indexed, for "as of" searches
+  #[serde(default)]
+  pub history: bool,
+}
+
+impl IndexState {
+  // the state after a reindex that began at `started`
+  fn new(
+    fingerprint: Fingerprint,
+    started: Instant,
+    analysis: &AnalysisConfig,
+    history: bool,
+  ) -> Self {
+    Self {
+      fingerprint,
+      reindexed_at: chrono::Utc::now(),
+      reindex_duration: started.elapsed(),
+      schema_version: SCHEMA_VERSION,
+      analyzers: Some(analysis.digest()),
+      history,
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -47,23 +195,66 @@ pub struct PageMatchResult {
 
 #[derive(Debug)]
 pub struct PageMatchEntry {
+  #[allow(unused)]
   pub namespace: String,
   pub title: MatchSnippet,
+  /// headings of the matching section, None for the lead section
+  pub section: Option<String>,
+  /// the matching revision, for "as of" searches
+  pub revision: Option<i64>,
   pub text: MatchSnippet,
   pub url: String,
+  #[allow(unused)]
   pub page_id: i64,
+  /// relevance, only comparable within the results of one index
+  pub score: Score,
 }
 
-#[derive(derive_more::Constructor, Debug)]
+#[derive(Debug)]
 pub struct MatchSnippet {
   source: String,
   snippet: Snippet,
+  /// words of the fragment matched by their pinyin
+  pinyin: Vec<Range<usize>>,
   max_length: usize,
 }
 
 impl MatchSnippet {
+  pub fn new(source: String, snippet: Snippet, max_length: usize) -> Self {
+    Self {
+      source,
+      snippet,
+      pinyin: vec![],
+      max_length,
+    }
+  }
+
+  // Add the words matched by their pinyin, given the generator of the
+  // query's pinyin terms. A source without other matches gets a snippet
+  // of them instead.
+  fn with_pinyin(mut self, generator: Option<&SnippetGenerator>) -> Self {
+    let Some(generator) = generator else {
+      return self;
+    };
+    if self.snippet.highlighted().is_empty() {
+      self.snippet = generator.snippet(&self.source);
+    } else {
+      // the fragment fits within the generator's maximum length, so its
+      // snippet starts at its start too
+      let snippet = generator.snippet(self.snippet.fragment());
+      self.pinyin = snippet.highlighted().to_vec();
+    }
+    self
+  }
+
+  // mark the matches in the snippet, including those of synonyms, as
+  // they are part of the expanded query the snippet was made for, and
+  // the words matched by their pinyin
   pub fn highlight(&self, prefix: &str, suffix: &str) -> String {
-    let highlights = collapse_overlapped_ranges(self.snippet.highlighted());
+    let mut ranges = self.snippet.highlighted().to_vec();
+    ranges.extend(self.pinyin.iter().cloned());
+    ranges.sort_by_key(|range| range.start);
+    let highlights = collapse_overlapped_ranges(&ranges);
     let fragment = self.snippet.fragment();
 
     if highlights.is_empty() {
@@ -125,10 +316,35 @@ pub struct QueryOptions {
   #[clap(long, value_parser = crate::util::parse_date)]
   pub date_after: Option<tantivy::DateTime>,
 
+  /// search pages created before this date
+  #[serde(default, deserialize_with = "crate::util::deserialize_date")]
+  #[clap(long, value_parser = crate::util::parse_date)]
+  pub created_before: Option<tantivy::DateTime>,
+
+  /// search pages created after this date
+  #[serde(default, deserialize_with = "crate::util::deserialize_date")]
+  #[clap(long, value_parser = crate::util::parse_date)]
+  pub created_after: Option<tantivy::DateTime>,
+
+  /// search pages created by this user
+  #[clap(long)]
+  #[serde(default)]
+  pub creator: Option<String>,
+
+  /// search pages last edited by this user
+  #[clap(long)]
+  #[serde(default)]
+  pub last_editor: Option<String>,
+
   /// fuzzy search
   #[clap(short('f'), long, default_value_t)]
   #[serde(default)]
   pub fuzzy: bool,
+
+  /// search the wiki as it looked on this date (requires history mode)
+  #[serde(default, deserialize_with = "crate::util::deserialize_date")]
+  #[clap(long, value_parser = crate::util::parse_date)]
+  pub as_of: Option<tantivy::DateTime>,
 }
 
 impl Default for QueryOptions {
@@ -139,7 +355,12 @@ impl Default for QueryOptions {
       snippet_length: 100,
       date_before: None,
       date_after: None,
+      created_before: None,
+      created_after: None,
+      creator: None,
+      last_editor: None,
       fuzzy: false,
+      as_of: None,
     }
   }
 }
@@ -151,83 +372,303 @@ impl QueryOptions {
 }
 
 impl Search {
-  pub fn new(index_dir: &Path) -> Result<Self> {
+  // Open the live generation of the index in the directory, or create
+  // the first generation of a new index, analyzing the fields with the
+  // given analyzers.
+  pub fn new(index_dir: &Path, analysis: AnalysisConfig) -> Result<Self> {
     if !index_dir.exists() {
       std::fs::create_dir_all(index_dir)?;
     }
 
-    let (fields, schema) = build_schema();
-    let dir = MmapDirectory::open(index_dir)
-      .map_err(|e| e.to_string())
-      .unwrap();
+    let (fields, schema) = build_schema(&analysis);
 
-    let index = Index::open_or_create(dir, schema.clone())?;
-    index.tokenizers().register("text", text_tokenizer());
-    index.tokenizers().register("casei", casei_tokenizer());
-    index
-      .fast_field_tokenizer()
-      .register("casei", casei_tokenizer());
+    let mut name = generation::current(index_dir)?;
+    let has_old_index = index_dir.join("meta.json").exists();
+    if name.is_none() && !has_old_index {
+      let first = generation::next_name(index_dir)?;
+      std::fs::create_dir_all(generation::path(index_dir, Some(&first)))?;
+      generation::set_current(index_dir, &first)?;
+      name = Some(first);
+    }
+    let live = Generation::open(index_dir, name, &schema, &analysis)?;
 
     Ok(Search {
       fields,
       schema,
-      index,
-      revision: 0,
+      analysis,
+      synonyms: Synonyms::default(),
+      index_dir: index_dir.to_path_buf(),
+      live: RwLock::new(Arc::new(live)),
+      writer_lock: Arc::new(tokio::sync::Mutex::new(())),
+      threads: util::default_threads(),
+      history: false,
     })
   }
 
-  pub fn requires_reindex(&self, latest_revision: u32) -> bool {
-    self.revision < latest_revision
+  pub fn with_threads(mut self, threads: usize) -> Self {
+    self.threads = threads.max(1);
+    self
   }
 
-  pub fn reindex_pages(
-    &mut self,
-    pages: impl IntoIterator<Item = Page>,
-    revision: u32,
-  ) -> Result<()> {
-    let mut writer = self.index.writer(128_000_000)?;
-    writer.delete_all_documents()?;
-    self.index_pages_with(&writer, pages)?;
-    writer.commit()?;
-    self.revision = revision;
-    Ok(())
+  pub fn with_synonyms(mut self, synonyms: Synonyms) -> Self {
+    self.synonyms = synonyms;
+    self
+  }
+
+  pub fn with_history(mut self, history: bool) -> Self {
+    self.history = history;
+    self
   }
 
-  fn index_page_with(&self, writer: &IndexWriter, page: Page) -> Result<()> {
-    writer.add_document(self.make_doc(page)?)?;
+  pub fn requires_reindex(&self, latest: &Fingerprint) -> bool {
+    self.fingerprint().as_ref() != Some(latest)
+  }
 
-    Ok(())
+  // None if the index is outdated, to rebuild it from scratch
+  pub fn fingerprint(&self) -> Option<Fingerprint> {
+    if self.live().outdated.is_some() {
+      return None;
+    }
+    self.index_state().map(|state| state.fingerprint)
+  }
+
+  // why the live index needs a rebuild, if it does
+  pub fn outdated(&self) -> Option<String> {
+    self.live().outdated.clone()
+  }
+
+  pub fn index_state(&self) -> Option<IndexState> {
+    self.live().state.lock().unwrap().clone()
+  }
+
+  fn live(&self) -> Arc<Generation> {
+    self.live.read().unwrap().clone()
+  }
+
+  // Open a writer to add or remove pages, waiting for any other
+  // writer to be committed or dropped first. Searches keep seeing the
+  // last commit while the writer is open.
+  pub async fn writer(&self) -> Result<SearchWriter> {
+    let lock = self.writer_lock.clone().lock_owned().await;
+    self.open_writer(self.live(), lock)
+  }
+
+  // Open a writer on a new, empty generation of the index, to rebuild
+  // it from scratch. Searches keep using the live generation until the
+  // new one is published with `Search::publish`.
+  pub async fn new_generation(&self) -> Result<SearchWriter> {
+    let lock = self.writer_lock.clone().lock_owned().await;
+    let name = generation::next_name(&self.index_dir)?;
+    std::fs::create_dir_all(generation::path(&self.index_dir, Some(&name)))?;
+    let generation = Generation::open(
+      &self.index_dir,
+      Some(name),
+      &self.schema,
+      &self.analysis,
+    )?;
+    self.open_writer(Arc::new(generation), lock)
+  }
+
+  fn open_writer(
+    &self,
+    generation: Arc<Generation>,
+    lock: OwnedMutexGuard<()>,
+  ) -> Result<SearchWriter> {
+    let writer = generation.index.writer_with_num_threads(
+      self.threads.min(MAX_WRITER_THREADS),
+      WRITER_MEMORY_BUDGET,
+    )?;
+
+    Ok(SearchWriter {
+      writer,
+      generation,
+      builder: DocBuilder {
+        fields: self.fields.clone(),
+      },
+      threads: self.threads,
+      _lock: lock,
+    })
+  }
+
+  // ids of all pages currently in the index
+  pub fn indexed_page_ids(&self) -> Result<HashSet<i64>> {
+    let searcher = self.live().index.reader()?.searcher();
+    let mut ids = HashSet::new();
+
+    for segment_reader in searcher.segment_readers() {
+      let column = segment_reader.fast_fields().i64("id")?;
+      for doc in segment_reader.doc_ids_alive() {
+        ids.extend(column.first(doc));
+      }
+    }
+
+    Ok(ids)
+  }
+
+  // Make the changes of the writer visible to searches. `started` is
+  // when the reindex began, including the time spent on listing pages.
+  pub fn commit(
+    &self,
+    mut writer: SearchWriter,
+    fingerprint: Fingerprint,
+    started: Instant,
+  ) -> Result<()> {
+    let state =
+      IndexState::new(fingerprint, started, &self.analysis, self.history);
+    writer.commit(Some(state))
+  }
+
+  // Make the changes of the writer visible without marking the index
+  // as up to date with the wiki, e.g. after updating a single page.
+  // The next reindex still looks for all changes since the last one.
+  pub fn commit_pages(&self, mut writer: SearchWriter) -> Result<()> {
+    let state = writer.generation.state.lock().unwrap().clone();
+    writer.commit(state)
   }
 
-  fn index_pages_with(
-    &mut self,
-    writer: &IndexWriter,
-    pages: impl IntoIterator<Item = Page>,
+  // Commit a generation built with `Search::new_generation`, check it
+  // and make it the live one. `page_count` is the number of pages
+  // added to it. A generation failing the checks is deleted, leaving
+  // the live generation in place.
+  pub fn publish(
+    &self,
+    mut writer: SearchWriter,
+    fingerprint: Fingerprint,
+    started: Instant,
+    page_count: usize,
   ) -> Result<()> {
-    for page in pages {
-      self.index_page_with(writer, page)?;
+    let generation = writer.generation.clone();
+    let Some(name) = generation.name.clone() else {
+      return self.commit(writer, fingerprint, started);
+    };
+
+    // the writer, and with it the writer lock, is kept until the new
+    // generation is live
+    let state =
+      IndexState::new(fingerprint, started, &self.analysis, self.history);
+    let checked = writer
+      .commit(Some(state))
+      .and_then(|()| self.check(&generation, page_count));
+    if let Err(e) = checked {
+      warn!("discarding index generation {}: {}", name, e);
+      drop((writer, generation));
+      let dir = generation::path(&self.index_dir, Some(&name));
+      let _ = std::fs::remove_dir_all(dir);
+      return Err(e);
+    }
+
+    self.switch_to(generation)
+  }
+
+  // Go back to the generation before the live one, or to the given
+  // one. Returns the name of the now live generation.
+  pub async fn rollback(&self, to: Option<&str>) -> Result<String> {
+    let _lock = self.writer_lock.lock().await;
+    let name = match (to, &self.live().name) {
+      (Some(name), _) => Some(name.to_string()),
+      (None, Some(current)) => generation::previous(&self.index_dir, current)?,
+      (None, None) => None,
+    };
+    let Some(name) = name else {
+      return Err(Error::Generic("no earlier index generation".into()));
+    };
+
+    let generation = Generation::open(
+      &self.index_dir,
+      Some(name.clone()),
+      &self.schema,
+      &self.analysis,
+    )?;
+    self.switch_to(Arc::new(generation))?;
+    Ok(name)
+  }
+
+  // point CURRENT to the generation, then direct searches to it
+  fn switch_to(&self, generation: Arc<Generation>) -> Result<()> {
+    let Some(name) = generation.name.clone() else {
+      return Err(Error::Generic("cannot switch to an unnamed index".into()));
+    };
+    generation::set_current(&self.index_dir, &name)?;
+    *self.live.write().unwrap() = generation;
+    info!("switched to index generation {}", name);
+
+    let keep = generation::KEPT_GENERATIONS;
+    generation::prune(&self.index_dir, &name, keep)
+  }
+
+  // Sanity checks of a new generation before it goes live: all pages
+  // made it into the index, and a page is found by its own title.
+  fn check(&self, generation: &Generation, page_count: usize) -> Result<()> {
+    let index = &generation.index;
+    let indexed = self.count_pages(index)?;
+    if indexed != page_count {
+      return Err(Error::Generic(format!(
+        "{indexed} pages in the new index instead of {page_count}"
+      )));
+    }
+
+    let Some(title) = self.sample_title(index)? else {
+      return Ok(());
+    };
+    let query = format!("\"{}\"", title.replace(['"', '\\'], " "));
+    let result = self.query_index(index, &query, &QueryOptions::default())?;
+    if result.entries.is_empty() {
+      return Err(Error::Generic(format!(
+        "the new index finds no page titled {title:?}"
+      )));
     }
 
     Ok(())
   }
 
+  // the title of an indexed page which has words to search for, not
+  // only stop words
+  fn sample_title(&self, index: &Index) -> Result<Option<String>> {
+    let searcher = index.reader()?.searcher();
+    let mut analyzer = index.tokenizer_for_field(self.fields.title)?;
+    let kind_query = TermQuery::new(
+      Term::from_field_text(self.fields.kind, KIND_CURRENT),
+      IndexRecordOption::Basic,
+    );
+    let top = searcher.search(&kind_query, &TopDocs::with_limit(100))?;
+    for (_score, addr) in top {
+      let title = text_field(&searcher.doc(addr)?, self.fields.title);
+      if analyzer.token_stream(&title).advance() {
+        return Ok(Some(title));
+      }
+    }
+    Ok(None)
+  }
+
   fn parse_query(
     &self,
+    index: &Index,
     query: &str,
     options: &QueryOptions,
   ) -> Result<Box<dyn Query>> {
     use tantivy::query::{BooleanQuery, QueryParser, RangeQuery};
-    let mut query_parser = QueryParser::for_index(
-      &self.index,
-      vec![self.fields.title, self.fields.text],
-    );
+    let mut default_fields = vec![
+      self.fields.title,
+      self.fields.alias,
+      self.fields.section,
+      self.fields.text,
+    ];
+    for localized in &self.fields.localized {
+      default_fields.extend([localized.title, localized.text]);
+    }
+    let mut query_parser = QueryParser::for_index(index, default_fields);
+    // an old or alternate name should find the real page first
+    query_parser.set_field_boost(self.fields.alias, ALIAS_BOOST);
 
     if options.fuzzy {
       query_parser.set_field_fuzzy(self.fields.title, true, 1, true);
+      query_parser.set_field_fuzzy(self.fields.alias, true, 1, true);
       query_parser.set_field_fuzzy(self.fields.text, true, 1, true);
     }
 
-    let query = query_parser.parse_query(query)?;
+    let query = expand_dates(query);
+    let query = self.synonyms.expand(&query);
+    let query = query_parser.parse_query(&pinyin::expand(&query))?;
 
     let to_bound = |d| match d {
       Some(d) => Bound::Included(d),
@@ -243,58 +684,76 @@ impl Search {
       Box::new(AllQuery)
     };
 
-    let query = BooleanQuery::intersection(vec![query, title_range_query]);
+    let kind = if options.as_of.is_some() {
+      KIND_HISTORY
+    } else {
+      KIND_CURRENT
+    };
+    let kind_query: Box<dyn Query> = Box::new(TermQuery::new(
+      Term::from_field_text(self.fields.kind, kind),
+      IndexRecordOption::Basic,
+    ));
+
+    let mut queries = vec![query, title_range_query, kind_query];
+
+    if options.created_after.is_some() || options.created_before.is_some() {
+      queries.push(Box::new(RangeQuery::new_date_bounds(
+        "created".into(),
+        to_bound(options.created_after),
+        to_bound(options.created_before),
+      )));
+    }
 
-    Ok(Box::new(query))
-  }
+    let users = [
+      (self.fields.creator, &options.creator),
+      (self.fields.last_editor, &options.last_editor),
+    ];
+    for (field, user) in users {
+      if let Some(user) = user {
+        queries.push(self.exact_query(index, field, user)?);
+      }
+    }
 
-  fn search_order_by_score(
-    &self,
-    searcher: &mut Searcher,
-    options: &QueryOptions,
-    query: &impl Query,
-  ) -> Result<(usize, Vec<DocAddress>)> {
-    use tantivy::collector::{Count, TopDocs};
-    let mut collector = MultiCollector::new();
-    let top_docs =
-      TopDocs::with_limit(options.count).and_offset(options.offset);
-    let top_docs_handle = collector.add_collector(top_docs);
-    let total_records_handle = collector.add_collector(Count);
-
-    let mut fruits = searcher.search(query, &collector)?;
-    let top_docs = top_docs_handle
-      .extract(&mut fruits)
-      .into_iter()
-      .map(|(_score, doc_addr)| doc_addr)
-      .collect();
-    let total_records = total_records_handle.extract(&mut fruits);
+    // the revision that was current at the end of the given day
+    if let Some(as_of) = options.as_of {
+      let end =
+        DateTime::from_timestamp_secs(as_of.into_timestamp_secs() + 86400);
+      queries.push(Box::new(RangeQuery::new_date_bounds(
+        "valid_from".into(),
+        Bound::Unbounded,
+        Bound::Excluded(end),
+      )));
+      queries.push(Box::new(RangeQuery::new_date_bounds(
+        "valid_until".into(),
+        Bound::Included(end),
+        Bound::Unbounded,
+      )));
+    }
 
-    Ok((total_records, top_docs))
+    let query = BooleanQuery::intersection(queries);
+
+    Ok(Box::new(query))
   }
 
-  fn search_order_by_date(
+  // match the whole value of a field indexed with the "casei"
+  // tokenizer, e.g. a user name
+  fn exact_query(
     &self,
-    searcher: &mut Searcher,
-    options: &QueryOptions,
-    query: &impl Query,
-  ) -> Result<(usize, Vec<DocAddress>)> {
-    use tantivy::collector::{Count, TopDocs};
-    let mut collector = MultiCollector::new();
-    let top_docs = TopDocs::with_limit(options.count)
-      .and_offset(options.offset)
-      .order_by_fast_field::<DateTime>("title_date", Order::Desc);
-    let top_docs_handle = collector.add_collector(top_docs);
-    let total_records_handle = collector.add_collector(Count);
-
-    let mut fruits = searcher.search(query, &collector)?;
-    let top_docs = top_docs_handle
-      .extract(&mut fruits)
-      .into_iter()
-      .map(|(_score, doc_addr)| doc_addr)
-      .collect();
-    let total_records = total_records_handle.extract(&mut fruits);
+    index: &Index,
+    field: Field,
+    value: &str,
+  ) -> Result<Box<dyn Query>> {
+    let mut analyzer = index.tokenizer_for_field(field)?;
+    let mut stream = analyzer.token_stream(value.trim());
+    let token = match stream.next() {
+      Some(token) => token.text.clone(),
+      None => String::new(),
+    };
 
-    Ok((total_records, top_docs))
+    Ok(Box::new(TermQuery::new(
+      Term::from_field_text(field, &token),
+      IndexRecordOption::Basic,
+    )))
   }
 
   fn search(
@@ -302,12 +761,15 @@ impl Search {
     searcher: &mut Searcher,
     options: &QueryOptions,
     query: &impl Query,
-  ) -> Result<(usize, Vec<DocAddress>)> {
-    if options.date_specified() {
-      self.search_order_by_date(searcher, options, query)
+  ) -> Result<(usize, Vec<(Score, DocAddress)>)> {
+    let order = if options.date_specified() {
+      PageOrder::TitleDate
     } else {
-      self.search_order_by_score(searcher, options, query)
-    }
+      PageOrder::Score
+    };
+
+    let collector = TopPages::new(options.offset, options.count, order);
+    Ok(searcher.search(query, &collector)?)
   }
 
   fn generate_docs(
@@ -315,7 +777,7 @@ impl Search {
     searcher: &mut Searcher,
     options: &QueryOptions,
     query: &impl Query,
-    top_docs: Vec<DocAddress>,
+    top_docs: Vec<(Score, DocAddress)>,
   ) -> Result<Vec<PageMatchEntry>> {
     let title_snippet_gen =
       SnippetGenerator::create(searcher, query, self.fields.title)?;
@@ -323,8 +785,24 @@ impl Search {
       SnippetGenerator::create(searcher, query, self.fields.text)?;
     text_snippet_gen.set_max_num_chars(options.snippet_length);
 
+    // the words matched by their pinyin, if the query has pinyin terms
+    let mut pinyin_terms = false;
+    query.query_terms(&mut |term, _| {
+      pinyin_terms |= term.field() == self.fields.pinyin;
+    });
+    let pinyin_snippet_gens = if pinyin_terms {
+      let title_gen =
+        SnippetGenerator::create(searcher, query, self.fields.pinyin)?;
+      let mut text_gen =
+        SnippetGenerator::create(searcher, query, self.fields.pinyin)?;
+      text_gen.set_max_num_chars(options.snippet_length);
+      Some((title_gen, text_gen))
+    } else {
+      None
+    };
+
     let mut entries = vec![];
-    for addr in top_docs {
+    for (score, addr) in top_docs {
       let doc = searcher.doc(addr)?;
       let page_id = doc.get_first(self.fields.id).unwrap().as_i64().unwrap();
       let namespace = text_field(&doc, self.fields.namespace);
@@ -333,20 +811,30 @@ impl Search {
         let source = text_field(&doc, self.fields.title);
         let snippet = title_snippet_gen.snippet_from_doc(&doc);
         MatchSnippet::new(source, snippet, options.snippet_length)
+          .with_pinyin(pinyin_snippet_gens.as_ref().map(|(gen, _)| gen))
       };
       let text = {
         let source = text_field(&doc, self.fields.text);
         let snippet = text_snippet_gen.snippet_from_doc(&doc);
         MatchSnippet::new(source, snippet, options.snippet_length)
+          .with_pinyin(pinyin_snippet_gens.as_ref().map(|(_, gen)| gen))
       };
       let url = text_field(&doc, self.fields.url);
+      let section = Some(text_field(&doc, self.fields.section))
+        .filter(|section| !section.is_empty());
+      let revision = doc
+        .get_first(self.fields.revision)
+        .and_then(|value| value.as_i64());
 
       entries.push(PageMatchEntry {
         namespace,
         title,
+        section,
+        revision,
         text,
         url,
         page_id,
+        score,
       });
     }
 
@@ -357,11 +845,30 @@ impl Search {
     &self,
     query: &str,
     options: &QueryOptions,
+  ) -> Result<PageMatchResult> {
+    let live = self.live();
+    // without past revisions, an "as of" search would find nothing
+    let history = live.state.lock().unwrap().as_ref().map(|s| s.history);
+    if options.as_of.is_some() && history != Some(true) {
+      return Err(Error::Generic(
+        "the index has no history, reindex it in history mode to search \
+         as of a date"
+          .into(),
+      ));
+    }
+    self.query_index(&live.index, query, options)
+  }
+
+  fn query_index(
+    &self,
+    index: &Index,
+    query: &str,
+    options: &QueryOptions,
   ) -> Result<PageMatchResult> {
     let start = std::time::Instant::now();
-    let mut searcher = self.index.reader()?.searcher();
+    let mut searcher = index.reader()?.searcher();
 
-    let query = self.parse_query(query, options)?;
+    let query = self.parse_query(index, query, options)?;
     let (total_records, top_docs) =
       self.search(&mut searcher, options, &query)?;
     let entries =
@@ -383,65 +890,377 @@ impl Search {
     })
   }
 
-  fn make_doc(&self, page: Page) -> Result<Document> {
-    let mut doc = Document::new();
+  // number of pages (not sections) in the index
+  pub fn page_count(&self) -> Result<usize> {
+    self.count_pages(&self.live().index)
+  }
+
+  fn count_pages(&self, index: &Index) -> Result<usize> {
+    let searcher = index.reader()?.searcher();
+    let lead_section = TermQuery::new(
+      Term::from_field_i64(self.fields.section_no, 0),
+      IndexRecordOption::Basic,
+    );
+    Ok(searcher.search(&lead_section, &Count)?)
+  }
+}
+
+impl SearchWriter {
+  // remove the documents of a page, including its past revisions.
+  // Documents added afterwards are kept.
+  pub fn remove_page(&self, id: i64) {
+    let id_field = self.builder.fields.id;
+    self.writer.delete_term(Term::from_field_i64(id_field, id));
+  }
+
+  // add documents built by the `DocBuilder` of this writer
+  pub fn add_docs(&self, docs: Vec<Document>) -> Result<()> {
+    for doc in docs {
+      self.writer.add_document(doc)?;
+    }
+
+    Ok(())
+  }
+
+  pub fn doc_builder(&self) -> DocBuilder {
+    self.builder.clone()
+  }
+
+  // number of documents to build at the same time
+  pub fn threads(&self) -> usize {
+    self.threads
+  }
+
+  // the state is saved as the payload of the commit, which only lasts
+  // until the next commit
+  fn commit(&mut self, state: Option<IndexState>) -> Result<()> {
+    let mut commit = self.writer.prepare_commit()?;
+    if let Some(state) = &state {
+      commit.set_payload(&serde_json::to_string(state)?);
+    }
+    commit.commit()?;
+
+    *self.generation.state.lock().unwrap() = state;
+    Ok(())
+  }
+}
+
+impl Generation {
+  // Open the index of a generation, creating it if needed. An index
+  // built by another version is opened as outdated; if its schema
+  // differs, it can't be searched at all and is replaced by an empty
+  // index in memory until it is rebuilt.
+  fn open(
+    index_dir: &Path,
+    name: Option<String>,
+    schema: &Schema,
+    analysis: &AnalysisConfig,
+  ) -> Result<Self> {
+    let path = generation::path(index_dir, name.as_deref());
+    let dir = MmapDirectory::open(&path).map_err(|e| {
+      Error::Generic(format!("cannot open {}: {e}", path.display()))
+    })?;
+
+    let exists = Index::exists(&dir).map_err(tantivy::TantivyError::from)?;
+    let (index, state, outdated) = if exists {
+      let index = Index::open(dir)?;
+      let state = load_state(&index)?;
+      let outdated = outdated_reason(&index, schema, analysis, state.as_ref())?;
+      (index, state, outdated)
+    } else {
+      let settings = IndexSettings::default();
+      (Index::create(dir, schema.clone(), settings)?, None, None)
+    };
+
+    if let Some(reason) = &outdated {
+      warn!(
+        "the index in {} is outdated ({}), the next reindex rebuilds it",
+        path.display(),
+        reason
+      );
+    }
+    let index = if index.schema() == *schema {
+      index
+    } else {
+      warn!("searches find nothing until the index is rebuilt");
+      Index::create_in_ram(schema.clone())
+    };
+
+    analysis.register(&index);
+
+    Ok(Self {
+      name,
+      index,
+      state: Mutex::new(state),
+      outdated,
+    })
+  }
+}
+
+// Why an existing index can't be used as is: it was built with another
+// schema, or with other analyzers as told by the version and the
+// analyzers digest saved with its last commit.
+fn outdated_reason(
+  index: &Index,
+  schema: &Schema,
+  analysis: &AnalysisConfig,
+  state: Option<&IndexState>,
+) -> Result<Option<String>> {
+  if index.schema() != *schema {
+    return Ok(Some("its schema differs".into()));
+  }
+
+  let Some(state) = state else {
+    // nothing was committed to it yet
+    if index.reader()?.searcher().num_docs() == 0 {
+      return Ok(None);
+    }
+    return Ok(Some(format!(
+      "built with index version 0 instead of {SCHEMA_VERSION}"
+    )));
+  };
+
+  let version = state.schema_version;
+  if version != SCHEMA_VERSION {
+    return Ok(Some(format!(
+      "built with index version {version} instead of {SCHEMA_VERSION}"
+    )));
+  }
+
+  let digest = state
+    .analyzers
+    .clone()
+    .unwrap_or_else(|| AnalysisConfig::default().digest());
+  if digest != analysis.digest() {
+    return Ok(Some("built with other analyzers".into()));
+  }
+
+  Ok(None)
+}
+
+impl Fields {
+  // the fields of the language, if it has any
+  fn localized(&self, language: Option<&Language>) -> Option<&LocalizedFields> {
+    let code = language?.code;
+    self
+      .localized
+      .iter()
+      .find(|localized| localized.code == code)
+  }
+}
+
+impl DocBuilder {
+  // each section of the page is indexed as its own document, with
+  // the page-level fields repeated
+  pub fn page_docs(&self, page: Page) -> Result<Vec<Document>> {
     let f = &self.fields;
 
-    doc.add_i64(f.id, page.id);
-    doc.add_text(f.title, page.title);
-    doc.add_text(f.text, page.text);
-    doc.add_text(f.url, page.url);
+    let texts = page.sections.iter().map(|section| section.text.as_str());
+    let language =
+      language::detect([page.title.as_str()].into_iter().chain(texts));
+    let localized = f.localized(language);
+
+    let mut page_doc = Document::new();
+    page_doc.add_i64(f.id, page.id);
+    page_doc.add_text(f.kind, KIND_CURRENT);
+    page_doc.add_text(f.title, &page.title);
+    page_doc.add_text(f.pinyin, pinyin::cjk_only(&page.title));
+    if let Some(language) = language {
+      page_doc.add_text(f.language, language.code);
+    }
+    if let Some(localized) = localized {
+      page_doc.add_text(localized.title, &page.title);
+    }
 
     if let Some(title_date) = page.title_date.timestamp() {
       let tantivy_date = DateTime::from_timestamp_secs(title_date);
-      doc.add_date(f.title_date, tantivy_date);
+      page_doc.add_date(f.title_date, tantivy_date);
     }
 
     let tantivy_date = DateTime::from_timestamp_secs(page.updated.timestamp());
-    doc.add_date(f.updated, tantivy_date);
-    doc.add_text(f.namespace, &page.namespace.to_string());
+    page_doc.add_date(f.updated, tantivy_date);
+
+    let created = DateTime::from_timestamp_secs(page.created.timestamp());
+    page_doc.add_date(f.created, created);
+    page_doc.add_text(f.creator, &page.creator);
+    page_doc.add_text(f.last_editor, &page.last_editor);
+    page_doc.add_i64(f.revision_count, page.revision_count);
+
+    for name in page.namespace.search_names() {
+      page_doc.add_text(f.namespace, name);
+    }
 
     for cat in page.categories.iter() {
-      doc.add_text(f.category, cat);
+      page_doc.add_text(f.category, cat);
+    }
+
+    for alias in page.aliases.iter() {
+      page_doc.add_text(f.alias, alias);
     }
 
-    Ok(doc)
+    // pages without any text still get an empty lead section
+    let lead = [Section::default()];
+    let sections = if page.sections.is_empty() {
+      &lead[..]
+    } else {
+      &page.sections[..]
+    };
+
+    let mut docs = vec![];
+    for (section_no, section) in sections.iter().enumerate() {
+      let mut doc = page_doc.clone();
+      doc.add_i64(f.section_no, section_no as i64);
+      doc.add_text(f.section, section.headings.join(" > "));
+      doc.add_text(f.text, &section.text);
+      doc.add_text(f.pinyin, pinyin::cjk_only(&section.text));
+      if let Some(localized) = localized {
+        doc.add_text(localized.text, &section.text);
+      }
+      doc.add_text(f.url, page.section_url(section));
+      docs.push(doc);
+    }
+
+    Ok(docs)
   }
 
-  pub fn page_count(&self) -> Result<usize> {
-    let reader = self.index.reader()?;
-    Ok(reader.searcher().num_docs() as usize)
+  // past revisions are indexed as a single document each
+  pub fn revision_doc(&self, revision: Revision) -> Document {
+    let f = &self.fields;
+    let mut doc = Document::new();
+
+    let language = language::detect([revision.title.as_str(), &revision.text]);
+    if let Some(language) = language {
+      doc.add_text(f.language, language.code);
+    }
+    if let Some(localized) = f.localized(language) {
+      doc.add_text(localized.title, &revision.title);
+      doc.add_text(localized.text, &revision.text);
+    }
+    doc.add_text(f.pinyin, pinyin::cjk_only(&revision.title));
+    doc.add_text(f.pinyin, pinyin::cjk_only(&revision.text));
+
+    doc.add_i64(f.id, revision.page_id);
+    doc.add_text(f.kind, KIND_HISTORY);
+    doc.add_i64(f.revision, revision.id);
+    doc.add_text(f.title, revision.title);
+    doc.add_text(f.section, "");
+    doc.add_text(f.text, revision.text);
+    doc.add_text(f.url, revision.url);
+    for name in revision.namespace.search_names() {
+      doc.add_text(f.namespace, name);
+    }
+    doc.add_text(f.actor, revision.actor);
+
+    if let Some(title_date) = revision.title_date.timestamp() {
+      let tantivy_date = DateTime::from_timestamp_secs(title_date);
+      doc.add_date(f.title_date, tantivy_date);
+    }
+
+    let valid_from = revision.timestamp.timestamp();
+    let valid_until = match &revision.valid_until {
+      Some(timestamp) => timestamp.timestamp(),
+      None => OPEN_END_TIMESTAMP,
+    };
+    doc.add_date(f.updated, DateTime::from_timestamp_secs(valid_from));
+    doc.add_date(f.valid_from, DateTime::from_timestamp_secs(valid_from));
+    doc.add_date(f.valid_until, DateTime::from_timestamp_secs(valid_until));
+
+    doc
   }
 }
 
-fn build_schema() -> (Fields, Schema) {
+// read back the state saved with the last commit, if any
+fn load_state(index: &Index) -> Result<Option<IndexState>> {
+  let Some(payload) = index.load_metas()?.payload else {
+    return Ok(None);
+  };
+
+  match serde_json::from_str(&payload) {
+    Ok(state) => Ok(Some(state)),
+    Err(e) => {
+      warn!("ignoring unrecognized index payload: {}", e);
+      Ok(None)
+    }
+  }
+}
+
+fn build_schema(analysis: &AnalysisConfig) -> (Fields, Schema) {
   use tantivy::schema::*;
 
   let mut schema_builder = Schema::builder();
 
-  let text_opt = TextOptions::default().set_stored().set_indexing_options(
+  // full text, with positions for phrase queries and snippets
+  let text_opt = |field: &str| {
+    TextOptions::default().set_stored().set_indexing_options(
+      TextFieldIndexing::default()
+        .set_tokenizer(analysis.field_analyzer(field))
+        .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+    )
+  };
+
+  // names, also kept as fast fields to sort and group by
+  let casei_opt = |field: &str| {
+    let analyzer = analysis.field_analyzer(field);
+    TextOptions::default()
+      .set_stored()
+      .set_fast(Some(analyzer))
+      .set_indexing_options(
+        TextFieldIndexing::default()
+          .set_tokenizer(analyzer)
+          .set_index_option(IndexRecordOption::WithFreqs),
+      )
+  };
+
+  let id = schema_builder.add_i64_field("id", INDEXED | STORED | FAST);
+  let title = schema_builder.add_text_field("title", text_opt("title"));
+  let text = schema_builder.add_text_field("text", text_opt("text"));
+  let title_date = schema_builder.add_date_field("title_date", STORED | FAST);
+  let updated = schema_builder.add_date_field("updated", STORED | FAST);
+  let created =
+    schema_builder.add_date_field("created", INDEXED | STORED | FAST);
+  let creator = schema_builder.add_text_field("creator", casei_opt("creator"));
+  let last_editor =
+    schema_builder.add_text_field("last_editor", casei_opt("last_editor"));
+  let revision_count =
+    schema_builder.add_i64_field("revision_count", INDEXED | STORED | FAST);
+  let namespace =
+    schema_builder.add_text_field("namespace", casei_opt("namespace"));
+  let url = schema_builder.add_text_field("url", STORED | STRING);
+  let category =
+    schema_builder.add_text_field("category", casei_opt("category"));
+  let section = schema_builder.add_text_field("section", text_opt("section"));
+  let alias = schema_builder.add_text_field("alias", text_opt("alias"));
+  let section_no =
+    schema_builder.add_i64_field("section_no", INDEXED | STORED | FAST);
+  let kind = schema_builder.add_text_field("kind", STRING);
+  let revision =
+    schema_builder.add_i64_field("revision", INDEXED | STORED | FAST);
+  let valid_from = schema_builder.add_date_field("valid_from", STORED | FAST);
+  let valid_until = schema_builder.add_date_field("valid_until", FAST);
+  let actor = schema_builder.add_text_field("actor", casei_opt("actor"));
+  let language = schema_builder.add_text_field("language", STRING | STORED);
+  let pinyin_opt = TextOptions::default().set_indexing_options(
     TextFieldIndexing::default()
-      .set_tokenizer("text")
+      .set_tokenizer(analysis.field_analyzer("pinyin"))
       .set_index_option(IndexRecordOption::WithFreqsAndPositions),
   );
+  let pinyin = schema_builder.add_text_field("pinyin", pinyin_opt);
 
-  let casei_opt = TextOptions::default()
-    .set_stored()
-    .set_fast(Some("casei"))
-    .set_indexing_options(
+  let mut localized = vec![];
+  for lang in language::LANGUAGES.iter().filter(|lang| lang.has_fields()) {
+    let localized_opt = TextOptions::default().set_indexing_options(
       TextFieldIndexing::default()
-        .set_tokenizer("casei")
-        .set_index_option(IndexRecordOption::WithFreqs),
+        .set_tokenizer(&lang.tokenizer_name())
+        .set_index_option(IndexRecordOption::WithFreqsAndPositions),
     );
-
-  let id = schema_builder.add_i64_field("id", STORED | FAST);
-  let title = schema_builder.add_text_field("title", text_opt.clone());
-  let text = schema_builder.add_text_field("text", text_opt);
-  let title_date = schema_builder.add_date_field("title_date", STORED | FAST);
-  let updated = schema_builder.add_date_field("updated", STORED | FAST);
-  let namespace = schema_builder.add_text_field("namespace", casei_opt.clone());
-  let url = schema_builder.add_text_field("url", STORED | STRING);
-  let category = schema_builder.add_text_field("category", casei_opt);
+    let title_name = format!("title_{}", lang.code);
+    let text_name = format!("text_{}", lang.code);
+    localized.push(LocalizedFields {
+      code: lang.code,
+      title: schema_builder.add_text_field(&title_name, localized_opt.clone()),
+      text: schema_builder.add_text_field(&text_name, localized_opt),
+    });
+  }
 
   let schema = schema_builder.build();
 
@@ -451,42 +1270,107 @@ fn build_schema() -> (Fields, Schema) {
     text,
     title_date,
     updated,
+    created,
+    creator,
+    last_editor,
+    revision_count,
     namespace,
     url,
     category,
+    section,
+    section_no,
+    alias,
+    kind,
+    revision,
+    valid_from,
+    valid_until,
+    actor,
+    language,
+    pinyin,
+    localized,
   };
 
   (fields, schema)
 }
 
-fn text_tokenizer() -> TextAnalyzer {
-  use tantivy::tokenizer::*;
-
-  // base: tokenize Chinese words
-  TextAnalyzer::builder(JiebaTokenizer)
-    // lowercase all words
-    .filter(LowerCaser)
-    // stem english words
-    .filter(Stemmer::new(Language::English))
-    // normalize unicode punctuations
-    .filter(AsciiFoldingFilter)
-    // remove long tokens (e.g. base64)
-    .filter(RemoveLongFilter::limit(32))
-    .build()
+// Date fields only accept RFC 3339 date times in tantivy's query
+// syntax. Bare dates in date field clauses are expanded to midnight
+// (UTC), and a single bare date matches the whole day, e.g.
+// `created:2020-01-01` or `created:[2020-01-01 TO 2021-01-01]`.
+fn expand_dates(query: &str) -> Cow<'_, str> {
+  static CLAUSE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+      r"\b(title_date|updated|created):",
+      r"([\[\{][^\]\}]*[\]\}]|[<>]?=?\d{4}-\d{2}-\d{2}[^\s()]*)"
+    ))
+    .unwrap()
+  });
+  static DATE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(\d{4}-\d{2}-\d{2})(T[^\s\]\}]*)?").unwrap());
+
+  CLAUSE.replace_all(query, |caps: &Captures| {
+    let (field, value) = (&caps[1], &caps[2]);
+
+    if let Ok(day) = Date::parse_from_str(value, "%Y-%m-%d") {
+      if let Some(next_day) = day.succ_opt() {
+        return format!("{field}:[{day}T00:00:00Z TO {next_day}T00:00:00Z}}");
+      }
+    }
+
+    let value = DATE.replace_all(value, |caps: &Captures| match caps.get(2) {
+      Some(_) => caps[0].to_string(),
+      None => format!("{}T00:00:00Z", &caps[1]),
+    });
+    format!("{field}:{value}")
+  })
+}
+
+// Merge the results of several indexes into one list, best first.
+// Scores are only comparable within one index, so each is divided by
+// the top score of its list first, scoring the best match of every
+// index 1.0.
+pub fn merge_by_score<K: Clone, T>(
+  lists: Vec<(K, Vec<T>)>,
+  score: impl Fn(&T) -> Score,
+) -> Vec<(K, T, Score)> {
+  let mut merged = vec![];
+  for (key, items) in lists {
+    let top = items.iter().map(&score).fold(0.0, Score::max);
+    for item in items {
+      let normalized = if top > 0.0 { score(&item) / top } else { 0.0 };
+      merged.push((key.clone(), item, normalized));
+    }
+  }
+
+  merged.sort_by(|a, b| b.2.total_cmp(&a.2));
+  merged
 }
 
-fn casei_tokenizer() -> TextAnalyzer {
-  use tantivy::tokenizer::*;
-
-  // base: do not tokenize
-  TextAnalyzer::builder(RawTokenizer::default())
-    // lowercase all words
-    .filter(LowerCaser)
-    // stem english words
-    .filter(Stemmer::new(Language::English))
-    // normalize unicode punctuations
-    .filter(AsciiFoldingFilter)
-    .build()
+// assuming ranges are sorted
+fn collapse_overlapped_ranges(ranges: &[Range<usize>]) -> Vec<Range<usize>> {
+  let mut result = Vec::new();
+  let mut ranges_it = ranges.iter();
+
+  let mut current = match ranges_it.next() {
+    Some(range) => range.clone(),
+    None => return result,
+  };
+
+  for range in ranges {
+    if current.end > range.start {
+      current = current.start..std::cmp::max(current.end, range.end);
+    } else {
+      result.push(current);
+      current = range.clone();
+    }
+  }
+
+  result.push(current);
+  result
+}
+
+fn text_field(doc: &Document, field: Field) -> String {
+  doc.get_first(field).unwrap().as_text().unwrap().to_string()
 }
 
 #[cfg(test)]
@@ -500,77 +1384,132 @@ mod test {
     "在1987年開始發展而成[1]。"
   );
 
+  #[test]
+  fn test_expand_dates() {
+    use super::expand_dates;
+
+    assert_eq!(
+      expand_dates("hiking created:[2020-01-01 TO 2021-01-01]"),
+      "hiking created:[2020-01-01T00:00:00Z TO 2021-01-01T00:00:00Z]"
+    );
+    assert_eq!(
+      expand_dates("updated:2023-01-02"),
+      "updated:[2023-01-02T00:00:00Z TO 2023-01-03T00:00:00Z}"
+    );
+    assert_eq!(
+      expand_dates("created:>=2020-01-01T12:00:00Z"),
+      "created:>=2020-01-01T12:00:00Z"
+    );
+    assert_eq!(expand_dates("diary 2023-01-02"), "diary 2023-01-02");
+  }
+
+  #[test]
+  fn test_outdated_reason() {
+    use super::{
+      build_schema, outdated_reason, AnalysisConfig, IndexState, SCHEMA_VERSION,
+    };
+    use tantivy::{schema::Schema, Index};
+
+    let analysis = AnalysisConfig::default();
+    let (_, schema) = build_schema(&analysis);
+    let index = Index::create_in_ram(schema.clone());
+    let reason =
+      |state| outdated_reason(&index, &schema, &analysis, state).unwrap();
+    assert_eq!(reason(None), None);
+
+    let started = std::time::Instant::now();
+    let state = IndexState::new(Default::default(), started, &analysis, false);
+    assert_eq!(state.schema_version, SCHEMA_VERSION);
+    assert_eq!(reason(Some(&state)), None);
+
+    // saved by a version that didn't record them
+    let mut payload = serde_json::to_value(&state).unwrap();
+    payload.as_object_mut().unwrap().remove("analyzers");
+    let old_state: IndexState = serde_json::from_value(payload).unwrap();
+    assert_eq!(reason(Some(&old_state)), None);
+
+    let mut payload = serde_json::to_value(&state).unwrap();
+    payload.as_object_mut().unwrap().remove("schema_version");
+    let old_state: IndexState = serde_json::from_value(payload).unwrap();
+    assert!(reason(Some(&old_state)).is_some());
+
+    let mut other_state = state.clone();
+    other_state.analyzers = Some("0000000000000000".into());
+    assert!(reason(Some(&other_state)).is_some());
+
+    let other = Index::create_in_ram(Schema::builder().build());
+    assert!(outdated_reason(&other, &schema, &analysis, None)
+      .unwrap()
+      .is_some());
+  }
+
+  #[test]
+  fn test_merge_by_score() {
+    let lists = vec![
+      ("a", vec![10.0, 5.0]),
+      ("b", vec![2.0, 1.5, 0.5]),
+      ("c", vec![]),
+    ];
+    let merged = super::merge_by_score(lists, |score: &f32| *score);
+    let order: Vec<_> = merged
+      .iter()
+      .map(|(key, score, _)| (*key, *score))
+      .collect();
+    assert_eq!(
+      order,
+      [("a", 10.0), ("b", 2.0), ("b", 1.5), ("a", 5.0), ("b", 0.5)]
+    );
+    assert_eq!(merged[0].2, 1.0);
+  }
+
   #[test]
   fn test_text_tokenizer() {
-    let tokenizer = super::text_tokenizer();
+    let tokenizer = super::AnalysisConfig::default().analyzer("text").unwrap();
 
     assert_eq!(
       tokenize(tokenizer, LOJBAN_SAMPLE_TEXT),
       vec![
-        "邏輯語",
-        "(",
         "逻辑",
         "语",
-        ":",
+        "逻辑",
+        "语",
         "la",
-        " ",
-        ".",
         "lojban",
-        ".",
-        ",",
-        "英語",
-        ":",
+        "英语",
         "lojban",
-        ",",
-        "/",
         "ˈ",
         "lo",
         "ʒ",
         "ban",
-        "/",
-        " ",
-        " ",
-        "(",
-        " ",
-        "聆",
-        "聽",
-        ")",
-        ")",
-        ",",
-        "一種",
+        "聆听",
+        "一种",
         "人工",
-        "語言",
-        ",",
-        "是",
+        "语言",
         "loglan",
-        "的",
-        "後",
-        "繼者",
-        ",",
-        "由",
-        "邏輯",
-        "語言群",
-        "(",
+        "后继",
+        "后继者",
+        "逻辑",
+        "语言",
+        "群",
         "logic",
-        " ",
         "languag",
-        " ",
         "group",
-        ",",
         "llg",
-        ")",
-        "在",
         "1987",
         "年",
-        "開始",
-        "發展",
+        "开始",
+        "发展",
         "而成",
-        "[",
-        "1",
-        "]",
-        "。"
+        "1"
       ]
     );
+
+    // either script finds the other
+    let analyzer = super::AnalysisConfig::default().analyzer("text").unwrap();
+    assert_eq!(
+      tokenize(analyzer.clone(), "邏輯語言"),
+      tokenize(analyzer, "逻辑语言")
+    );
   }
 
   fn tokenize(tokenizer: impl Into<TextAnalyzer>, s: &str) -> Vec<String> {
@@ -585,30 +1524,3 @@ mod test {
     tokens
   }
 }
-
-// assuming ranges are sorted
-fn collapse_overlapped_ranges(ranges: &[Range<usize>]) -> Vec<Range<usize>> {
-  let mut result = Vec::new();
-  let mut ranges_it = ranges.iter();
-
-  let mut current = match ranges_it.next() {
-    Some(range) => range.clone(),
-    None => return result,
-  };
-
-  for range in ranges {
-    if current.end > range.start {
-      current = current.start..std::cmp::max(current.end, range.end);
-    } else {
-      result.push(current);
-      current = range.clone();
-    }
-  }
-
-  result.push(current);
-  result
-}
-
-fn text_field(doc: &Document, field: Field) -> String {
-  doc.get_first(field).unwrap().as_text().unwrap().to_string()
-}
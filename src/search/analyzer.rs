@@ -0,0 +1,350 @@
+// Analyzers, configured by name. The built-in analyzers ("text" for
+// the full-text fields, "casei" for names matched as a whole, "pinyin"
+// for the pinyin of the text, see `pinyin`, and one per language, see
+// `language`) can be overridden and new ones added
+// in an analyzers file, which also picks the analyzer of each field.
+// The analyzers are registered with every index opened, and a digest
+// of them is saved with each commit, so that an index analyzed
+// differently is rebuilt.
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tantivy::{
+  tokenizer::{
+    AlphaNumOnlyFilter, AsciiFoldingFilter, Language as Stemming, LowerCaser,
+    RawTokenizer, RemoveLongFilter, SimpleTokenizer, Stemmer, StopWordFilter,
+    TextAnalyzer, WhitespaceTokenizer,
+  },
+  Index,
+};
+use tantivy_jieba::JiebaTokenizer;
+
+use super::{
+  filter::{self, PunctuationFilter, SimplifiedFilter},
+  language::{self, JapaneseTokenizer},
+  pinyin::PinyinFilter,
+};
+use crate::util::{Error, Result};
+
+// fields whose analyzer can be picked, and their default analyzer
+const TEXT_FIELDS: [&str; 4] = ["title", "alias", "section", "text"];
+const CASEI_FIELDS: [&str; 5] =
+  ["creator", "last_editor", "namespace", "category", "actor"];
+
+/// The analyzers file, e.g.
+///
+/// ```toml
+/// [analyzer.text]
+/// tokenizer = "jieba"
+/// filters = [
+///   "traditional_to_simplified",
+///   "punctuation",
+///   "lowercase",
+///   { stop_words = ["a", "an", "the"] },
+///   { stop_words = "zh" },
+///   { stemmer = "English" },
+///   "ascii_folding",
+///   { remove_long = 40 },
+/// ]
+///
+/// [analyzer.exact]
+/// tokenizer = "whitespace"
+/// filters = ["lowercase"]
+///
+/// [fields]
+/// alias = "exact"
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnalysisConfig {
+  #[serde(default)]
+  analyzer: BTreeMap<String, AnalyzerConfig>,
+  /// the analyzer of each field, by name
+  #[serde(default)]
+  fields: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnalyzerConfig {
+  pub tokenizer: TokenizerConfig,
+  #[serde(default)]
+  pub filters: Vec<FilterConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenizerConfig {
+  /// Chinese words, and words of other languages split at spaces and
+  /// punctuation
+  Jieba,
+  /// Japanese words, by lindera
+  Japanese,
+  /// words split at anything but letters and digits
+  Simple,
+  /// words split at whitespace
+  Whitespace,
+  /// the whole text as a single token
+  Raw,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterConfig {
+  /// convert traditional Chinese characters to simplified ones, before
+  /// the text is cut into words
+  TraditionalToSimplified,
+  /// drop tokens of whitespace and punctuation only
+  Punctuation,
+  Lowercase,
+  /// fold letters to their ASCII equivalent, e.g. "é" to "e"
+  AsciiFolding,
+  /// drop tokens with anything but ASCII letters and digits
+  AlphaNumOnly,
+  /// drop stop words, see `StopWords`
+  StopWords(StopWords),
+  /// Snowball stemmer of the language, e.g. `{ stemmer = "German" }`
+  Stemmer(Stemming),
+  /// replace words with CJK characters by their pinyin, in full and by
+  /// initials
+  Pinyin,
+  /// drop tokens longer than this many bytes
+  RemoveLong(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StopWords {
+  /// the built-in stop words of a language by its code, e.g. "en" or
+  /// "zh"
+  Language(String),
+  /// the given words, in lowercase
+  Words(Vec<String>),
+}
+
+impl Default for AnalysisConfig {
+  fn default() -> Self {
+    use FilterConfig::*;
+
+    let mut analyzer = BTreeMap::new();
+    analyzer.insert(
+      "text".into(),
+      AnalyzerConfig {
+        tokenizer: TokenizerConfig::Jieba,
+        filters: vec![
+          TraditionalToSimplified,
+          Punctuation,
+          Lowercase,
+          StopWords(self::StopWords::Language("en".into())),
+          StopWords(self::StopWords::Language("zh".into())),
+          Stemmer(Stemming::English),
+          AsciiFolding,
+          // e.g. base64
+          RemoveLong(32),
+        ],
+      },
+    );
+    analyzer.insert(
+      "casei".into(),
+      AnalyzerConfig {
+        tokenizer: TokenizerConfig::Raw,
+        filters: vec![Lowercase, Stemmer(Stemming::English), AsciiFolding],
+      },
+    );
+    analyzer.insert(
+      "pinyin".into(),
+      AnalyzerConfig {
+        tokenizer: TokenizerConfig::Jieba,
+        filters: vec![TraditionalToSimplified, Punctuation, Lowercase, Pinyin],
+      },
+    );
+    for lang in language::LANGUAGES {
+      if let Some(config) = lang.analyzer_config() {
+        analyzer.insert(lang.tokenizer_name(), config);
+      }
+    }
+
+    let text_fields = TEXT_FIELDS.map(|field| (field, "text"));
+    let casei_fields = CASEI_FIELDS.map(|field| (field, "casei"));
+    let fields = text_fields
+      .into_iter()
+      .chain(casei_fields)
+      .chain([("pinyin", "pinyin")])
+      .map(|(field, name)| (field.to_string(), name.to_string()))
+      .collect();
+
+    Self { analyzer, fields }
+  }
+}
+
+impl AnalysisConfig {
+  // the built-in analyzers, overridden and extended by the file
+  pub fn load(path: &Path) -> Result<Self> {
+    let content = std::fs::read_to_string(path)?;
+    let file: AnalysisConfig = toml::from_str(&content)?;
+
+    for (name, analyzer) in &file.analyzer {
+      for filter in &analyzer.filters {
+        if let FilterConfig::StopWords(StopWords::Language(code)) = filter {
+          if filter::stop_words(code).is_none() {
+            return Err(Error::Generic(format!(
+              "analyzer {name}: no built-in stop words for {code}"
+            )));
+          }
+        }
+      }
+    }
+
+    let mut config = Self::default();
+    config.analyzer.extend(file.analyzer);
+    for (field, name) in file.fields {
+      if !config.fields.contains_key(&field) {
+        return Err(Error::Generic(format!(
+          "the analyzer of field {field} can't be set"
+        )));
+      }
+      if !config.analyzer.contains_key(&name) {
+        return Err(Error::Generic(format!(
+          "field {field} uses undefined analyzer {name}"
+        )));
+      }
+      config.fields.insert(field, name);
+    }
+    Ok(config)
+  }
+
+  // the name of the analyzer of a field
+  pub fn field_analyzer(&self, field: &str) -> &str {
+    &self.fields[field]
+  }
+
+  #[cfg(test)]
+  pub fn analyzer(&self, name: &str) -> Option<TextAnalyzer> {
+    self.analyzer.get(name).map(AnalyzerConfig::build)
+  }
+
+  // register all analyzers with the index, for indexing and for fast
+  // fields
+  pub fn register(&self, index: &Index) {
+    for (name, config) in &self.analyzer {
+      index.tokenizers().register(name, config.build());
+      index.fast_field_tokenizer().register(name, config.build());
+    }
+  }
+
+  // Identifies the analyzers and the fields using them: a hash of
+  // their settings (FNV-1a, which unlike std's hasher is stable).
+  pub fn digest(&self) -> String {
+    let settings = serde_json::to_string(self).expect("serializable");
+    let hash = settings.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+      (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+  }
+}
+
+impl AnalyzerConfig {
+  pub fn build(&self) -> TextAnalyzer {
+    let mut builder = match self.tokenizer {
+      TokenizerConfig::Jieba => TextAnalyzer::builder(JiebaTokenizer).dynamic(),
+      TokenizerConfig::Japanese => {
+        TextAnalyzer::builder(JapaneseTokenizer).dynamic()
+      }
+      TokenizerConfig::Simple => {
+        TextAnalyzer::builder(SimpleTokenizer::default()).dynamic()
+      }
+      TokenizerConfig::Whitespace => {
+        TextAnalyzer::builder(WhitespaceTokenizer::default()).dynamic()
+      }
+      TokenizerConfig::Raw => {
+        TextAnalyzer::builder(RawTokenizer::default()).dynamic()
+      }
+    };
+
+    for filter in &self.filters {
+      builder = match filter {
+        FilterConfig::TraditionalToSimplified => {
+          builder.filter_dynamic(SimplifiedFilter)
+        }
+        FilterConfig::Punctuation => builder.filter_dynamic(PunctuationFilter),
+        FilterConfig::Lowercase => builder.filter_dynamic(LowerCaser),
+        FilterConfig::AsciiFolding => {
+          builder.filter_dynamic(AsciiFoldingFilter)
+        }
+        FilterConfig::AlphaNumOnly => {
+          builder.filter_dynamic(AlphaNumOnlyFilter)
+        }
+        FilterConfig::StopWords(StopWords::Language(code)) => {
+          match filter::stop_words(code) {
+            Some(stop_words) => builder.filter_dynamic(stop_words),
+            // checked when loaded
+            None => builder,
+          }
+        }
+        FilterConfig::StopWords(StopWords::Words(words)) => {
+          builder.filter_dynamic(StopWordFilter::remove(words.clone()))
+        }
+        FilterConfig::Stemmer(stemming) => {
+          builder.filter_dynamic(Stemmer::new(*stemming))
+        }
+        FilterConfig::Pinyin => builder.filter_dynamic(PinyinFilter),
+        FilterConfig::RemoveLong(limit) => {
+          builder.filter_dynamic(RemoveLongFilter::limit(*limit))
+        }
+      };
+    }
+
+    builder.build()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::AnalysisConfig;
+
+  #[test]
+  fn test_load() {
+    let dir = std::env::temp_dir()
+      .join(format!("wiki-search-analyzers-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("analyzers.toml");
+
+    std::fs::write(
+      &path,
+      r#"
+        [analyzer.text]
+        tokenizer = "simple"
+        filters = ["lowercase", { stemmer = "German" }]
+
+        [analyzer.exact]
+        tokenizer = "whitespace"
+        filters = ["lowercase"]
+
+        [fields]
+        alias = "exact"
+      "#,
+    )
+    .unwrap();
+    let config = AnalysisConfig::load(&path).unwrap();
+    let default = AnalysisConfig::default();
+    assert_eq!(config.field_analyzer("alias"), "exact");
+    assert_eq!(config.field_analyzer("title"), "text");
+    assert_eq!(config.analyzer["casei"], default.analyzer["casei"]);
+    assert_ne!(config.digest(), default.digest());
+    assert_eq!(default.digest(), AnalysisConfig::default().digest());
+
+    let mut analyzer = config.analyzer("text").unwrap();
+    let mut stream = analyzer.token_stream("Die Häuser");
+    let mut tokens = vec![];
+    while let Some(token) = stream.next() {
+      tokens.push(token.text.clone());
+    }
+    assert_eq!(tokens, ["die", "haus"]);
+
+    std::fs::write(&path, "[fields]\ntitle = \"missing\"\n").unwrap();
+    assert!(AnalysisConfig::load(&path).is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
@@ -0,0 +1,214 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use tantivy::{
+  collector::{Collector, SegmentCollector},
+  fastfield::Column,
+  DateTime, DocAddress, DocId, Score, SegmentOrdinal, SegmentReader,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageOrder {
+  Score,
+  TitleDate,
+}
+
+// Collects the top matching section documents, but only keeps the
+// best matching section of each page, so that a long page with many
+// matching sections doesn't flood the results.
+pub struct TopPages {
+  offset: usize,
+  limit: usize,
+  order: PageOrder,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+  score: Score,
+  title_date: Option<DateTime>,
+  addr: DocAddress,
+}
+
+pub struct TopPagesSegmentCollector {
+  segment_ord: SegmentOrdinal,
+  ids: Column<i64>,
+  title_dates: Column<DateTime>,
+  hits: HashMap<i64, Hit>,
+}
+
+impl TopPages {
+  pub fn new(offset: usize, limit: usize, order: PageOrder) -> Self {
+    Self {
+      offset,
+      limit,
+      order,
+    }
+  }
+
+  fn compare(&self, a: &Hit, b: &Hit) -> Ordering {
+    let by_score = b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal);
+    match self.order {
+      PageOrder::Score => by_score,
+      PageOrder::TitleDate => b.title_date.cmp(&a.title_date).then(by_score),
+    }
+  }
+}
+
+impl Collector for TopPages {
+  /// number of matching pages and the top section of each page, with
+  /// its score
+  type Fruit = (usize, Vec<(Score, DocAddress)>);
+  type Child = TopPagesSegmentCollector;
+
+  fn for_segment(
+    &self,
+    segment_ord: SegmentOrdinal,
+    reader: &SegmentReader,
+  ) -> tantivy::Result<Self::Child> {
+    let ids = reader.fast_fields().i64("id")?;
+    let title_dates = reader.fast_fields().date("title_date")?;
+
+    Ok(TopPagesSegmentCollector {
+      segment_ord,
+      ids,
+      title_dates,
+      hits: HashMap::new(),
+    })
+  }
+
+  fn requires_scoring(&self) -> bool {
+    // the score picks the best section even when ordered by date
+    true
+  }
+
+  fn merge_fruits(
+    &self,
+    segment_fruits: Vec<HashMap<i64, Hit>>,
+  ) -> tantivy::Result<Self::Fruit> {
+    let mut pages: HashMap<i64, Hit> = HashMap::new();
+    for (page_id, hit) in segment_fruits.into_iter().flatten() {
+      keep_best(&mut pages, page_id, hit);
+    }
+
+    let total = pages.len();
+    let mut hits: Vec<Hit> = pages.into_values().collect();
+    // only the pages up to the requested ones need sorting
+    let wanted = self.offset + self.limit;
+    if wanted < hits.len() {
+      hits.select_nth_unstable_by(wanted, |a, b| self.compare(a, b));
+      hits.truncate(wanted);
+    }
+    hits.sort_by(|a, b| self.compare(a, b));
+
+    let top = hits
+      .into_iter()
+      .skip(self.offset)
+      .map(|hit| (hit.score, hit.addr))
+      .collect();
+
+    Ok((total, top))
+  }
+}
+
+impl SegmentCollector for TopPagesSegmentCollector {
+  type Fruit = HashMap<i64, Hit>;
+
+  fn collect(&mut self, doc: DocId, score: Score) {
+    let Some(page_id) = self.ids.first(doc) else {
+      return;
+    };
+
+    let hit = Hit {
+      score,
+      title_date: self.title_dates.first(doc),
+      addr: DocAddress::new(self.segment_ord, doc),
+    };
+    keep_best(&mut self.hits, page_id, hit);
+  }
+
+  fn harvest(self) -> Self::Fruit {
+    self.hits
+  }
+}
+
+fn keep_best(hits: &mut HashMap<i64, Hit>, page_id: i64, hit: Hit) {
+  match hits.get(&page_id) {
+    Some(existing) if existing.score >= hit.score => {}
+    _ => {
+      hits.insert(page_id, hit);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use tantivy::{
+    doc,
+    query::{Query, TermQuery},
+    schema::{IndexRecordOption, Schema, FAST, TEXT},
+    DateTime, Index, Term,
+  };
+
+  use super::{PageOrder, TopPages};
+
+  #[test]
+  fn test_top_pages() {
+    let mut schema_builder = Schema::builder();
+    let id = schema_builder.add_i64_field("id", FAST);
+    let title_date = schema_builder.add_date_field("title_date", FAST);
+    let text = schema_builder.add_text_field("text", TEXT);
+    let index = Index::create_in_ram(schema_builder.build());
+
+    // two sections of each of 10 pages, in two segments: the first one
+    // matches more the later the page, the second one barely. Only the
+    // sections of the first one have a title date, the earlier the
+    // later the page.
+    let mut writer = index.writer_with_num_threads(1, 50_000_000).unwrap();
+    for page in 0..10i64 {
+      writer
+        .add_document(doc!(
+          id => page,
+          title_date => DateTime::from_timestamp_secs((10 - page) * 86400),
+          text => "a ".repeat(page as usize + 2),
+        ))
+        .unwrap();
+    }
+    writer.commit().unwrap();
+    for page in 0..10i64 {
+      writer
+        .add_document(doc!(id => page, text => "a b c"))
+        .unwrap();
+    }
+    writer.commit().unwrap();
+
+    let searcher = index.reader().unwrap().searcher();
+    let top = |query: &dyn Query, order| {
+      let collector = TopPages::new(1, 3, order);
+      let (total, hits) = searcher.search(query, &collector).unwrap();
+      // the segments are in no particular order
+      let pages: Vec<(i64, bool)> = hits
+        .into_iter()
+        .map(|(_, addr)| {
+          let reader = searcher.segment_reader(addr.segment_ord);
+          let ids = reader.fast_fields().i64("id").unwrap();
+          let dates = reader.fast_fields().date("title_date").unwrap();
+          let first = dates.first(addr.doc_id).is_some();
+          (ids.first(addr.doc_id).unwrap(), first)
+        })
+        .collect();
+      (total, pages)
+    };
+
+    let query = TermQuery::new(
+      Term::from_field_text(text, "a"),
+      IndexRecordOption::WithFreqs,
+    );
+    assert_eq!(
+      top(&query, PageOrder::Score),
+      (10, vec![(8, true), (7, true), (6, true)])
+    );
+    assert_eq!(
+      top(&query, PageOrder::TitleDate),
+      (10, vec![(1, true), (2, true), (3, true)])
+    );
+  }
+}
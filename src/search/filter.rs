@@ -0,0 +1,254 @@
+// Token filters of our own, used by the analyzers (see `analyzer`).
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use tantivy::tokenizer::{
+  Language as Stemming, StopWordFilter, Token, TokenFilter, TokenStream,
+  Tokenizer,
+};
+
+use super::language;
+
+/// Drops tokens of whitespace and punctuation only, which Jieba and
+/// lindera emit between words. Unlike removed stop words, they don't
+/// keep their positions: the following tokens move up, so that a
+/// phrase matches whatever punctuation or spacing separates its words.
+#[derive(Clone)]
+pub struct PunctuationFilter;
+
+impl TokenFilter for PunctuationFilter {
+  type Tokenizer<T: Tokenizer> = PunctuationFilterWrapper<T>;
+
+  fn transform<T: Tokenizer>(
+    self,
+    tokenizer: T,
+  ) -> PunctuationFilterWrapper<T> {
+    PunctuationFilterWrapper(tokenizer)
+  }
+}
+
+#[derive(Clone)]
+pub struct PunctuationFilterWrapper<T>(T);
+
+impl<T: Tokenizer> Tokenizer for PunctuationFilterWrapper<T> {
+  type TokenStream<'a> = PunctuationFilterStream<T::TokenStream<'a>>;
+
+  fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+    PunctuationFilterStream {
+      tail: self.0.token_stream(text),
+      removed: 0,
+      position: None,
+    }
+  }
+}
+
+pub struct PunctuationFilterStream<T> {
+  tail: T,
+  // number of tokens dropped so far
+  removed: usize,
+  // the position of the current token before it moved up, which
+  // tokenizers like `SimpleTokenizer` count the next one from
+  position: Option<usize>,
+}
+
+impl<T: TokenStream> TokenStream for PunctuationFilterStream<T> {
+  fn advance(&mut self) -> bool {
+    if let Some(position) = self.position.take() {
+      self.tail.token_mut().position = position;
+    }
+    while self.tail.advance() {
+      let token = self.tail.token_mut();
+      if token.text.chars().any(char::is_alphanumeric) {
+        self.position = Some(token.position);
+        token.position -= self.removed;
+        return true;
+      }
+      self.removed += 1;
+    }
+    false
+  }
+
+  fn token(&self) -> &Token {
+    self.tail.token()
+  }
+
+  fn token_mut(&mut self) -> &mut Token {
+    self.tail.token_mut()
+  }
+}
+
+// the simplified form of each traditional character, see the file
+static SIMPLIFIED: Lazy<HashMap<char, char>> = Lazy::new(|| {
+  let single = |s: &str| {
+    let mut chars = s.chars();
+    chars.next().filter(|_| chars.next().is_none())
+  };
+
+  let table = include_str!("data/ts_characters.txt");
+  table
+    .lines()
+    .filter(|line| !line.starts_with('#'))
+    .filter_map(|line| {
+      let (traditional, candidates) = line.split_once('\t')?;
+      let traditional = single(traditional)?;
+      let simplified = single(candidates.split(' ').next()?)?;
+      // a different length would move the offsets of the tokens
+      (traditional.len_utf8() == simplified.len_utf8())
+        .then_some((traditional, simplified))
+    })
+    .collect()
+});
+
+/// Converts traditional Chinese characters to simplified ones, so that
+/// text in either script finds the other. The text is converted before
+/// the tokenizer cuts it into words, so that both are cut alike, and
+/// characters are only replaced by characters as long in UTF-8, so that
+/// the offsets of the tokens, and thus the snippets, still point at the
+/// original characters.
+#[derive(Clone)]
+pub struct SimplifiedFilter;
+
+impl TokenFilter for SimplifiedFilter {
+  type Tokenizer<T: Tokenizer> = SimplifiedFilterWrapper<T>;
+
+  fn transform<T: Tokenizer>(self, tokenizer: T) -> SimplifiedFilterWrapper<T> {
+    SimplifiedFilterWrapper {
+      inner: tokenizer,
+      text: String::new(),
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct SimplifiedFilterWrapper<T> {
+  inner: T,
+  // the converted text being tokenized
+  text: String,
+}
+
+impl<T: Tokenizer> Tokenizer for SimplifiedFilterWrapper<T> {
+  type TokenStream<'a> = T::TokenStream<'a>;
+
+  fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+    self.text.clear();
+    self
+      .text
+      .extend(text.chars().map(|c| *SIMPLIFIED.get(&c).unwrap_or(&c)));
+    self.inner.token_stream(&self.text)
+  }
+}
+
+// The built-in stop words of a language by its code, e.g. "en", if
+// it has any. Stop words are matched after lowercasing and before
+// stemming.
+pub fn stop_words(code: &str) -> Option<StopWordFilter> {
+  let words: &[&str] = match code {
+    "zh" => CHINESE_STOP_WORDS,
+    "ja" => JAPANESE_STOP_WORDS,
+    "en" => return StopWordFilter::new(Stemming::English),
+    code => {
+      let language = language::LANGUAGES.iter().find(|l| l.code == code)?;
+      return StopWordFilter::new(language.stemming()?);
+    }
+  };
+  Some(StopWordFilter::remove(words.iter().map(|w| w.to_string())))
+}
+
+// function words, in simplified and traditional characters
+#[rustfmt::skip]
+const CHINESE_STOP_WORDS: &[&str] = &[
+  "的", "地", "得", "了", "着", "著", "过", "過", "是",
+  "在", "和", "与", "與", "及", "或", "而", "也", "都",
+  "就", "又", "还", "還", "把", "被", "给", "給", "对",
+  "對", "从", "從", "向", "于", "於", "为", "為", "以",
+  "之", "其", "则", "則", "且", "并", "並", "但", "即",
+  "由", "自", "等", "将", "將", "已", "这", "這", "那",
+  "个", "個", "们", "們", "这个", "這個", "那个", "那個",
+  "这些", "這些", "那些", "一个", "一個", "吗", "嗎", "呢",
+  "吧", "啊", "呀", "哦", "嘛", "么", "麼", "因为", "因為",
+  "所以", "如果", "虽然", "雖然", "但是", "而且", "或者", "以及",
+];
+
+// particles and auxiliaries, from Lucene's Japanese stop words
+#[rustfmt::skip]
+const JAPANESE_STOP_WORDS: &[&str] = &[
+  "の", "に", "は", "を", "た", "が", "で", "て", "と",
+  "し", "れ", "さ", "ある", "いる", "も", "する", "から",
+  "な", "こと", "として", "い", "や", "れる", "など", "なっ",
+  "ない", "この", "ため", "その", "あっ", "よう", "また", "もの",
+  "という", "あり", "まで", "られ", "なる", "へ", "か", "だ",
+  "これ", "によって", "により", "おり", "より", "による", "ず",
+  "なり", "られる", "において", "ば", "なかっ", "なく", "しかし",
+  "について", "せ", "だっ", "できる", "それ", "う", "ので",
+  "なお", "のみ", "でき", "き", "つ", "における", "および",
+  "いう", "さらに", "でも", "ら", "たり", "に関する", "たち",
+  "ます", "ん", "なら", "に対して", "せる", "及び", "これら",
+  "とき", "では", "にて", "ほか", "ながら", "うち", "そして",
+  "とともに", "ただし", "それぞれ", "または", "お", "ほど", "ものの",
+  "に対する", "と共に", "といった", "です", "とも", "ところ",
+  "ここ",
+];
+
+#[cfg(test)]
+mod test {
+  use tantivy::tokenizer::{
+    SimpleTokenizer, TextAnalyzer, WhitespaceTokenizer,
+  };
+
+  use super::{stop_words, PunctuationFilter, SimplifiedFilter};
+
+  #[test]
+  fn test_filters() {
+    let positions = |mut analyzer: TextAnalyzer, text: &str| {
+      let mut stream = analyzer.token_stream(text);
+      let mut tokens = vec![];
+      while let Some(token) = stream.next() {
+        tokens.push((token.text.clone(), token.position));
+      }
+      tokens
+    };
+
+    let analyzer = TextAnalyzer::builder(WhitespaceTokenizer::default())
+      .filter(PunctuationFilter)
+      .build();
+    assert_eq!(
+      positions(analyzer, "logical , language -- group"),
+      [
+        ("logical".into(), 0),
+        ("language".into(), 1),
+        ("group".into(), 2)
+      ]
+    );
+
+    // stop words leave a gap
+    let analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+      .filter(stop_words("en").unwrap())
+      .build();
+    assert_eq!(
+      positions(analyzer, "state of the art"),
+      [("state".into(), 0), ("art".into(), 3)]
+    );
+
+    // offsets of the original characters
+    let mut analyzer = TextAnalyzer::builder(WhitespaceTokenizer::default())
+      .filter(SimplifiedFilter)
+      .build();
+    let mut stream = analyzer.token_stream("邏輯 語言 lojban");
+    let mut tokens = vec![];
+    while let Some(token) = stream.next() {
+      tokens.push((token.text.clone(), token.offset_from));
+    }
+    assert_eq!(
+      tokens,
+      [
+        ("逻辑".into(), 0),
+        ("语言".into(), 7),
+        ("lojban".into(), 14)
+      ]
+    );
+
+    assert!(stop_words("el").is_none());
+    assert!(stop_words("de").is_some());
+  }
+}
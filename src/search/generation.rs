@@ -0,0 +1,147 @@
+// Generations of the index on disk. Each full rebuild writes a fresh
+// index into its own subdirectory of the index directory, e.g.
+// "gen-000003", and a CURRENT file names the live one. Switching the
+// file is atomic, so a crashed rebuild never touches the live index,
+// and the previous generations are kept for a rollback.
+//
+// An index built before generations existed lives directly in the
+// index directory, and stays live until the first full rebuild.
+
+use std::{
+  fs,
+  io::{ErrorKind, Write},
+  path::{Path, PathBuf},
+};
+
+use tracing::warn;
+
+use crate::util::{Error, Result};
+
+const CURRENT_FILE: &str = "CURRENT";
+const PREFIX: &str = "gen-";
+
+// number of previous generations kept for rollback
+pub const KEPT_GENERATIONS: usize = 2;
+
+// the directory of a generation, or the index directory itself for an
+// index without generations
+pub fn path(index_dir: &Path, name: Option<&str>) -> PathBuf {
+  match name {
+    Some(name) => index_dir.join(name),
+    None => index_dir.to_path_buf(),
+  }
+}
+
+// the name of the live generation, if any
+pub fn current(index_dir: &Path) -> Result<Option<String>> {
+  match fs::read_to_string(index_dir.join(CURRENT_FILE)) {
+    Ok(name) => Ok(Some(name.trim().to_string())),
+    Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(e.into()),
+  }
+}
+
+// Make the generation the live one. The new CURRENT file is written
+// aside and renamed over the old one, so readers see either.
+pub fn set_current(index_dir: &Path, name: &str) -> Result<()> {
+  if !path(index_dir, Some(name)).is_dir() {
+    return Err(Error::Generic(format!("no index generation {name}")));
+  }
+
+  let tmp = index_dir.join(format!("{CURRENT_FILE}.tmp"));
+  let mut file = fs::File::create(&tmp)?;
+  writeln!(file, "{name}")?;
+  file.sync_all()?;
+  fs::rename(&tmp, index_dir.join(CURRENT_FILE))?;
+  Ok(())
+}
+
+// names of all generations, oldest first
+pub fn list(index_dir: &Path) -> Result<Vec<String>> {
+  let mut names = vec![];
+  for entry in fs::read_dir(index_dir)? {
+    let entry = entry?;
+    let Some(name) = entry.file_name().to_str().map(String::from) else {
+      continue;
+    };
+    if number(&name).is_some() && entry.file_type()?.is_dir() {
+      names.push(name);
+    }
+  }
+  names.sort_by_key(|name| number(name));
+  Ok(names)
+}
+
+// the name for a new generation, after all existing ones
+pub fn next_name(index_dir: &Path) -> Result<String> {
+  let last = list(index_dir)?.last().and_then(|name| number(name));
+  Ok(format!("{PREFIX}{:06}", last.map_or(1, |n| n + 1)))
+}
+
+// the generation before the live one, to roll back to
+pub fn previous(index_dir: &Path, current: &str) -> Result<Option<String>> {
+  let older = list(index_dir)?
+    .into_iter()
+    .rev()
+    .find(|name| number(name) < number(current));
+  Ok(older)
+}
+
+// Delete all but the live generation and the `keep` generations right
+// before it. Generations after the live one are left over from failed
+// or abandoned rebuilds, or were rolled back from.
+pub fn prune(index_dir: &Path, current: &str, keep: usize) -> Result<()> {
+  let names = list(index_dir)?;
+  let Some(live) = names.iter().position(|name| name == current) else {
+    return Ok(());
+  };
+
+  let kept = live.saturating_sub(keep)..=live;
+  for (i, name) in names.iter().enumerate() {
+    if kept.contains(&i) {
+      continue;
+    }
+    if let Err(e) = fs::remove_dir_all(path(index_dir, Some(name))) {
+      warn!("cannot remove index generation {}: {}", name, e);
+    }
+  }
+  Ok(())
+}
+
+fn number(name: &str) -> Option<u64> {
+  name.strip_prefix(PREFIX)?.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{current, list, next_name, previous, prune, set_current};
+
+  #[test]
+  fn test_generations() {
+    let dir = std::env::temp_dir()
+      .join(format!("wiki-search-generations-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    assert_eq!(current(&dir).unwrap(), None);
+    assert_eq!(next_name(&dir).unwrap(), "gen-000001");
+    assert!(set_current(&dir, "gen-000001").is_err());
+
+    for _ in 0..5 {
+      let name = next_name(&dir).unwrap();
+      std::fs::create_dir(dir.join(&name)).unwrap();
+    }
+    std::fs::create_dir(dir.join("gen-unrelated")).unwrap();
+
+    set_current(&dir, "gen-000004").unwrap();
+    assert_eq!(current(&dir).unwrap().as_deref(), Some("gen-000004"));
+    let previous_name = previous(&dir, "gen-000004").unwrap();
+    assert_eq!(previous_name.as_deref(), Some("gen-000003"));
+
+    prune(&dir, "gen-000004", 1).unwrap();
+    assert_eq!(list(&dir).unwrap(), ["gen-000003", "gen-000004"]);
+    assert_eq!(next_name(&dir).unwrap(), "gen-000005");
+
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
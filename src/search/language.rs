@@ -0,0 +1,246 @@
+// Languages with analyzers of their own. The language of each page is
+// detected from its text while indexing and stored in the "language"
+// field. Pages in a language that needs a different analysis than the
+// default one of the "title" and "text" fields (Jieba and the English
+// stemmer, which fits Chinese and English) get their title and text
+// indexed a second time in fields of the language, e.g. "title_de" and
+// "text_de": Japanese is cut into words by lindera's morphological
+// analysis with the embedded IPADIC dictionary, and European languages
+// are stemmed by their Snowball stemmer. Queries search all of them.
+
+use std::borrow::Cow;
+
+use lindera::{
+  dictionary::{load_embedded_dictionary, DictionaryKind},
+  mode::Mode,
+  segmenter::Segmenter,
+};
+use once_cell::sync::Lazy;
+use tantivy::tokenizer::{Language as Stemming, Token, TokenStream, Tokenizer};
+use tracing::warn;
+use whatlang::Lang;
+
+use super::{
+  analyzer::{self, AnalyzerConfig, FilterConfig, TokenizerConfig},
+  filter,
+};
+
+// only the start of long pages is looked at to detect their language
+const SAMPLE_LENGTH: usize = 2000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Language {
+  /// ISO 639-1 code, the value of the "language" field
+  pub code: &'static str,
+  lang: Lang,
+  analysis: Analysis,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Analysis {
+  /// the default analyzer of the text fields
+  Default,
+  Japanese,
+  Snowball(Stemming),
+}
+
+const fn language(
+  code: &'static str,
+  lang: Lang,
+  analysis: Analysis,
+) -> Language {
+  Language {
+    code,
+    lang,
+    analysis,
+  }
+}
+
+pub const LANGUAGES: &[Language] = &[
+  language("zh", Lang::Cmn, Analysis::Default),
+  language("en", Lang::Eng, Analysis::Default),
+  language("ja", Lang::Jpn, Analysis::Japanese),
+  language("da", Lang::Dan, Analysis::Snowball(Stemming::Danish)),
+  language("de", Lang::Deu, Analysis::Snowball(Stemming::German)),
+  language("el", Lang::Ell, Analysis::Snowball(Stemming::Greek)),
+  language("es", Lang::Spa, Analysis::Snowball(Stemming::Spanish)),
+  language("fi", Lang::Fin, Analysis::Snowball(Stemming::Finnish)),
+  language("fr", Lang::Fra, Analysis::Snowball(Stemming::French)),
+  language("hu", Lang::Hun, Analysis::Snowball(Stemming::Hungarian)),
+  language("it", Lang::Ita, Analysis::Snowball(Stemming::Italian)),
+  language("nl", Lang::Nld, Analysis::Snowball(Stemming::Dutch)),
+  language("no", Lang::Nob, Analysis::Snowball(Stemming::Norwegian)),
+  language("pt", Lang::Por, Analysis::Snowball(Stemming::Portuguese)),
+  language("ro", Lang::Ron, Analysis::Snowball(Stemming::Romanian)),
+  language("ru", Lang::Rus, Analysis::Snowball(Stemming::Russian)),
+  language("sv", Lang::Swe, Analysis::Snowball(Stemming::Swedish)),
+];
+
+impl Language {
+  // whether the language has fields of its own
+  pub fn has_fields(&self) -> bool {
+    self.analysis != Analysis::Default
+  }
+
+  // the Snowball stemmer of the language's fields, if it has one
+  pub fn stemming(&self) -> Option<Stemming> {
+    match self.analysis {
+      Analysis::Snowball(stemming) => Some(stemming),
+      _ => None,
+    }
+  }
+
+  pub fn tokenizer_name(&self) -> String {
+    format!("text_{}", self.code)
+  }
+
+  // the built-in analyzer of the language's fields, if it has any
+  pub fn analyzer_config(&self) -> Option<AnalyzerConfig> {
+    use FilterConfig::*;
+
+    let stop_words = || {
+      filter::stop_words(self.code)
+        .map(|_| StopWords(analyzer::StopWords::Language(self.code.into())))
+    };
+    let config = match self.analysis {
+      Analysis::Default => return None,
+      Analysis::Japanese => AnalyzerConfig {
+        tokenizer: TokenizerConfig::Japanese,
+        filters: [Punctuation, Lowercase]
+          .into_iter()
+          .chain(stop_words())
+          .chain([AsciiFolding, RemoveLong(32)])
+          .collect(),
+      },
+      Analysis::Snowball(stemming) => AnalyzerConfig {
+        tokenizer: TokenizerConfig::Simple,
+        filters: [Lowercase]
+          .into_iter()
+          .chain(stop_words())
+          .chain([Stemmer(stemming), AsciiFolding, RemoveLong(32)])
+          .collect(),
+      },
+    };
+    Some(config)
+  }
+}
+
+// The language of a text given in parts, e.g. the title and sections
+// of a page, if it can be told reliably.
+pub fn detect<'a>(
+  parts: impl IntoIterator<Item = &'a str>,
+) -> Option<&'static Language> {
+  let mut sample = String::new();
+  for part in parts {
+    if sample.len() >= SAMPLE_LENGTH {
+      break;
+    }
+    sample.push_str(part);
+    sample.push('\n');
+  }
+
+  let info = whatlang::detect(&sample).filter(|info| info.is_reliable())?;
+  LANGUAGES
+    .iter()
+    .find(|language| language.lang == info.lang())
+}
+
+static SEGMENTER: Lazy<Segmenter> = Lazy::new(|| {
+  let dictionary = load_embedded_dictionary(DictionaryKind::IPADIC)
+    .expect("the IPADIC dictionary is embedded");
+  Segmenter::new(Mode::Normal, dictionary, None)
+});
+
+/// Cuts Japanese text into words with lindera. The dictionary is
+/// loaded on first use.
+#[derive(Clone)]
+pub struct JapaneseTokenizer;
+
+pub struct JapaneseTokenStream {
+  tokens: Vec<Token>,
+  // one past the current token
+  index: usize,
+}
+
+impl Tokenizer for JapaneseTokenizer {
+  type TokenStream<'a> = JapaneseTokenStream;
+
+  fn token_stream<'a>(&'a mut self, text: &'a str) -> JapaneseTokenStream {
+    let tokens = match SEGMENTER.segment(Cow::Borrowed(text)) {
+      Ok(tokens) => tokens
+        .into_iter()
+        .enumerate()
+        .map(|(position, token)| Token {
+          offset_from: token.byte_start,
+          offset_to: token.byte_end,
+          position,
+          text: token.surface.into_owned(),
+          position_length: 1,
+        })
+        .collect(),
+      Err(e) => {
+        warn!("cannot segment Japanese text: {}", e);
+        vec![]
+      }
+    };
+
+    JapaneseTokenStream { tokens, index: 0 }
+  }
+}
+
+impl TokenStream for JapaneseTokenStream {
+  fn advance(&mut self) -> bool {
+    if self.index < self.tokens.len() {
+      self.index += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn token(&self) -> &Token {
+    &self.tokens[self.index - 1]
+  }
+
+  fn token_mut(&mut self) -> &mut Token {
+    &mut self.tokens[self.index - 1]
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{detect, LANGUAGES};
+
+  #[test]
+  fn test_detect() {
+    let code = |text| detect([text]).map(|language| language.code);
+
+    assert_eq!(
+      code("Die Katze sitzt auf dem Dach und schaut den Vögeln zu."),
+      Some("de")
+    );
+    assert_eq!(
+      code("東京都に住んでいます。毎日電車で会社に通っています。"),
+      Some("ja")
+    );
+    assert_eq!(code("今天天气很好，我们一起去公园散步吧。"), Some("zh"));
+    assert_eq!(code("Kubernetes"), None);
+  }
+
+  #[test]
+  fn test_analyzers() {
+    let tokens = |code: &str, text: &str| {
+      let language = LANGUAGES.iter().find(|l| l.code == code).unwrap();
+      let mut analyzer = language.analyzer_config().unwrap().build();
+      let mut stream = analyzer.token_stream(text);
+      let mut tokens = vec![];
+      while let Some(token) = stream.next() {
+        tokens.push(token.text.clone());
+      }
+      tokens
+    };
+
+    assert_eq!(tokens("de", "Die Häuser der Stadt"), ["haus", "stadt"]);
+    assert!(tokens("ja", "東京都に住んでいます").contains(&"東京".into()));
+  }
+}
@@ -0,0 +1,330 @@
+// Pinyin, the romanization of Chinese. The CJK words of the title and
+// text of each page are indexed a second time in the "pinyin" field as
+// their pinyin, in full and by initials, e.g. 逻辑 (or 邏輯) as "luoji"
+// and "lj", so that they can be found without typing the characters.
+// Queries search the field for the terms that may be pinyin, see
+// `expand`, and the snippets highlight the characters of the words
+// found by their pinyin, as the field's analyzer keeps their offsets.
+
+use std::{
+  borrow::Cow,
+  collections::{HashMap, HashSet},
+};
+
+use once_cell::sync::Lazy;
+use regex::Captures;
+use tantivy::tokenizer::{Token, TokenFilter, TokenStream, Tokenizer};
+
+use super::synonyms::UNIT;
+
+// weight of the pinyin of a term relative to the term itself
+const PINYIN_BOOST: f32 = 0.5;
+
+// a word of characters with several readings is indexed as at most
+// this many of their combinations
+const MAX_READINGS: usize = 4;
+
+// the readings of each character, the most common first
+static READINGS: Lazy<HashMap<char, Vec<&'static str>>> = Lazy::new(|| {
+  let table = include_str!("data/pinyin.txt");
+  let mut readings: HashMap<char, Vec<&str>> = HashMap::new();
+  for line in table.lines().filter(|line| !line.starts_with('#')) {
+    let Some((syllable, chars)) = line.split_once(' ') else {
+      continue;
+    };
+    for c in chars.chars() {
+      let syllables = readings.entry(c).or_default();
+      if !syllables.contains(&syllable) {
+        syllables.push(syllable);
+      }
+    }
+  }
+  readings
+});
+
+// the syllables of pinyin, without the few that have no vowel, like
+// "ng", which are rarely typed
+static SYLLABLES: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+  READINGS
+    .values()
+    .flatten()
+    .copied()
+    .filter(|syllable| syllable.contains(['a', 'e', 'i', 'o', 'u', 'v']))
+    .collect()
+});
+
+// the longest syllable, e.g. "zhuang"
+const MAX_SYLLABLE: usize = 6;
+
+// the letters initials are indexed with, see `transliterate`, but for
+// the vowels of syllables like "ai", which would let most words pass
+const INITIALS: &str = "bcdfghjklmnpqrstwxyz";
+// whether the character is a CJK ideograph
+pub fn is_cjk(c: char) -> bool {
+  matches!(c,
+    '\u{3400}'..='\u{4dbf}'
+    | '\u{4e00}'..='\u{9fff}'
+    | '\u{f900}'..='\u{faff}'
+    | '\u{20000}'..='\u{2ffff}')
+}
+
+// The value of the pinyin field for a text: its CJK characters, with
+// the others blanked out so that words of other languages aren't
+// indexed again.
+pub fn cjk_only(text: &str) -> String {
+  text
+    .chars()
+    .map(|c| if is_cjk(c) { c } else { ' ' })
+    .collect()
+}
+
+// The pinyin of a word: in full, and by initials if it has several
+// syllables, for each way of reading it. Characters without a known
+// reading are kept as they are.
+pub fn transliterate(word: &str) -> Vec<String> {
+  // the full pinyin and initials of the start of the word, per reading
+  let mut readings = vec![(String::new(), String::new())];
+  let mut syllables = 0;
+
+  for c in word.chars() {
+    let own = c.to_string();
+    let choices = match READINGS.get(&c) {
+      Some(choices) => {
+        syllables += 1;
+        choices.clone()
+      }
+      None => vec![own.as_str()],
+    };
+
+    let mut next = vec![];
+    for (full, initials) in &readings {
+      for choice in choices.iter().take(MAX_READINGS - next.len()) {
+        let initial = choice.chars().next().unwrap_or_default();
+        next.push((format!("{full}{choice}"), format!("{initials}{initial}")));
+      }
+    }
+    readings = next;
+  }
+
+  let (full, initials): (Vec<String>, Vec<String>) =
+    readings.into_iter().unzip();
+  let mut words: Vec<String> = vec![];
+  for word in full
+    .into_iter()
+    .chain(initials.into_iter().filter(|_| syllables > 1))
+  {
+    if !words.contains(&word) {
+      words.push(word);
+    }
+  }
+  words
+}
+
+// Whether a word may be pinyin: a sequence of syllables, e.g.
+// "luoji", or of initials, e.g. "lj", in any case.
+fn is_pinyin(word: &str) -> bool {
+  let word = word.to_ascii_lowercase();
+  if word.len() < 2 || !word.bytes().all(|b| b.is_ascii_lowercase()) {
+    return false;
+  }
+  if word.chars().all(|c| INITIALS.contains(c)) {
+    return true;
+  }
+
+  // whether the word up to each position splits into syllables
+  let mut splits = vec![false; word.len() + 1];
+  splits[0] = true;
+  for end in 1..=word.len() {
+    splits[end] = (end.saturating_sub(MAX_SYLLABLE)..end)
+      .any(|start| splits[start] && SYLLABLES.contains(&word[start..end]));
+  }
+  splits[word.len()]
+}
+
+// Also search the pinyin field for the terms which may be pinyin, see
+// `is_pinyin`, e.g. `luoji` becomes `(luoji OR pinyin:luoji^0.5)`.
+// Other words, like most English ones, are left as they are, so that
+// they don't match the pinyin of unrelated CJK words. Terms in a field,
+// with a modifier or as a prefix, and phrases are left as they are too.
+pub fn expand(query: &str) -> Cow<'_, str> {
+  UNIT.replace_all(query, |caps: &Captures| {
+    let unit = caps[0].to_string();
+    if caps.name("field").is_some() || caps.name("modifier").is_some() {
+      return unit;
+    }
+    let Some(term) = caps.name("term").map(|term| term.as_str()) else {
+      return unit;
+    };
+
+    if !is_pinyin(term) || ["AND", "OR", "NOT"].contains(&term) {
+      return unit;
+    }
+    format!("({unit} OR pinyin:{unit}^{PINYIN_BOOST})")
+  })
+}
+
+/// Replaces the words with CJK characters by their pinyin, see
+/// `transliterate`, all at the position and offsets of the word. Other
+/// words pass as they are, so that pinyin in queries is searched as
+/// typed.
+#[derive(Clone)]
+pub struct PinyinFilter;
+
+impl TokenFilter for PinyinFilter {
+  type Tokenizer<T: Tokenizer> = PinyinFilterWrapper<T>;
+
+  fn transform<T: Tokenizer>(self, tokenizer: T) -> PinyinFilterWrapper<T> {
+    PinyinFilterWrapper(tokenizer)
+  }
+}
+
+#[derive(Clone)]
+pub struct PinyinFilterWrapper<T>(T);
+
+impl<T: Tokenizer> Tokenizer for PinyinFilterWrapper<T> {
+  type TokenStream<'a> = PinyinFilterStream<T::TokenStream<'a>>;
+
+  fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+    PinyinFilterStream {
+      tail: self.0.token_stream(text),
+      pending: vec![],
+    }
+  }
+}
+
+pub struct PinyinFilterStream<T> {
+  tail: T,
+  // the pinyin of the current word left to emit, last first
+  pending: Vec<String>,
+}
+
+impl<T: TokenStream> TokenStream for PinyinFilterStream<T> {
+  fn advance(&mut self) -> bool {
+    if let Some(text) = self.pending.pop() {
+      self.tail.token_mut().text = text;
+      return true;
+    }
+    if !self.tail.advance() {
+      return false;
+    }
+
+    let token = self.tail.token_mut();
+    if token.text.chars().any(is_cjk) {
+      let mut words = transliterate(&token.text);
+      words.reverse();
+      token.text = words.pop().unwrap_or_default();
+      self.pending = words;
+    }
+    true
+  }
+
+  fn token(&self) -> &Token {
+    self.tail.token()
+  }
+
+  fn token_mut(&mut self) -> &mut Token {
+    self.tail.token_mut()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use tantivy::{
+    collector::Count,
+    doc,
+    query::QueryParser,
+    schema::{IndexRecordOption, Schema, TextFieldIndexing, TextOptions, TEXT},
+    tokenizer::{TextAnalyzer, WhitespaceTokenizer},
+    Index,
+  };
+
+  use super::{expand, is_pinyin, transliterate, PinyinFilter};
+
+  #[test]
+  fn test_pinyin() {
+    assert_eq!(transliterate("逻辑"), ["luoji", "lj"]);
+    assert_eq!(transliterate("邏輯"), ["luoji", "lj"]);
+    assert_eq!(transliterate("行"), ["xing", "hang"]);
+
+    let mut analyzer = TextAnalyzer::builder(WhitespaceTokenizer::default())
+      .filter(PinyinFilter)
+      .build();
+    let mut stream = analyzer.token_stream("逻辑 lojban");
+    let mut tokens = vec![];
+    while let Some(token) = stream.next() {
+      tokens.push((token.text.clone(), token.position, token.offset_to));
+    }
+    assert_eq!(
+      tokens,
+      [
+        ("luoji".into(), 0, 6),
+        ("lj".into(), 0, 6),
+        ("lojban".into(), 1, 13)
+      ]
+    );
+
+    assert_eq!(
+      expand("luoji AND k8s -lj title:lj yuyan^2 \"luoji\""),
+      concat!(
+        "(luoji OR pinyin:luoji^0.5) AND k8s -(lj OR pinyin:lj^0.5)",
+        " title:lj yuyan^2 \"luoji\""
+      )
+    );
+    assert_eq!(
+      expand("Luoji program the am"),
+      "(Luoji OR pinyin:Luoji^0.5) program the am"
+    );
+  }
+
+  #[test]
+  fn test_is_pinyin() {
+    assert!(is_pinyin("luoji"));
+    assert!(is_pinyin("zhuangxiang"));
+    assert!(is_pinyin("xian"));
+    assert!(is_pinyin("LJ"));
+    assert!(!is_pinyin("program"));
+    assert!(!is_pinyin("the"));
+    assert!(!is_pinyin("am"));
+    assert!(!is_pinyin("l"));
+    assert!(!is_pinyin("k8s"));
+  }
+
+  // an English word that happens to be the initials of a CJK word
+  // doesn't find its page
+  #[test]
+  fn test_english_query() {
+    let mut schema = Schema::builder();
+    let indexing = TextFieldIndexing::default()
+      .set_tokenizer("pinyin")
+      .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+    let text = schema.add_text_field("text", TEXT);
+    let pinyin = schema.add_text_field(
+      "pinyin",
+      TextOptions::default().set_indexing_options(indexing),
+    );
+    let index = Index::create_in_ram(schema.build());
+    index.tokenizers().register(
+      "pinyin",
+      TextAnalyzer::builder(WhitespaceTokenizer::default())
+        .filter(PinyinFilter)
+        .build(),
+    );
+
+    let mut writer = index.writer_with_num_threads(1, 15_000_000).unwrap();
+    // amen, read "amen" and "am" by initials
+    writer
+      .add_document(doc!(text => "", pinyin => "阿门"))
+      .unwrap();
+    writer.commit().unwrap();
+
+    let searcher = index.reader().unwrap().searcher();
+    let parser = QueryParser::for_index(&index, vec![text]);
+    let count = |query: &str| {
+      let query = parser.parse_query(&expand(query)).unwrap();
+      searcher.search(&query, &Count).unwrap()
+    };
+    assert_eq!(count("amen"), 1);
+    assert_eq!(count("am"), 0);
+    assert_eq!(count("pinyin:am"), 1);
+  }
+}
@@ -0,0 +1,218 @@
+// Synonyms expanding queries. A term or phrase of a query containing a
+// word of a synonym group is also searched with the word replaced by
+// each other word of the group, e.g. `k8s` becomes
+// `(k8s OR "kubernetes"^0.5 OR "容器编排"^0.5)` and `k8s集群` becomes
+// `(k8s集群 OR "kubernetes集群"^0.5 OR "容器编排集群"^0.5)`. The
+// expansion happens on the query text before it is parsed, so the
+// synonyms are analyzed like the words typed, and the snippets mark
+// their matches too.
+
+use std::{borrow::Cow, collections::HashMap, path::Path};
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+use crate::util::Result;
+
+// weight of a synonym relative to the word typed
+const SYNONYM_BOOST: f32 = 0.5;
+
+// A term, phrase or range of a query, with the field it searches and
+// its modifier (boost or fuzziness), if any.
+pub(super) static UNIT: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(concat!(
+    r"(?:(?P<field>\w+):)?",
+    r#"(?:"(?P<phrase>[^"]*)"|(?P<range>[\[\{][^\]\}]*[\]\}])"#,
+    r#"|(?P<term>[^\s()"\[\]{}:^~+\-][^\s()"\[\]{}:^~]*))"#,
+    r"(?P<modifier>[\^~][^\s()]*)?"
+  ))
+  .unwrap()
+});
+
+/// The synonyms file, e.g.
+///
+/// ```toml
+/// synonyms = [
+///   ["k8s", "kubernetes", "容器编排"],
+///   ["db", "database", "数据库"],
+/// ]
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SynonymsFile {
+  synonyms: Vec<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Synonyms {
+  /// the other words of the groups of each word, by normalized word
+  alternatives: HashMap<String, Vec<String>>,
+  /// any word of the groups, None without synonyms
+  pattern: Option<Regex>,
+}
+
+impl Synonyms {
+  pub fn load(path: &Path) -> Result<Self> {
+    let content = std::fs::read_to_string(path)?;
+    let file: SynonymsFile = toml::from_str(&content)?;
+    Ok(Self::new(file.synonyms))
+  }
+
+  pub fn new(groups: Vec<Vec<String>>) -> Self {
+    let mut alternatives: HashMap<String, Vec<String>> = HashMap::new();
+    for group in groups {
+      // quotes would end the phrases of the expanded query
+      let words: Vec<String> = group
+        .iter()
+        .map(|word| word.replace('"', " ").trim().to_string())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+      for word in &words {
+        let others = alternatives.entry(normalize(word)).or_default();
+        for other in &words {
+          if normalize(other) != normalize(word) && !others.contains(other) {
+            others.push(other.clone());
+          }
+        }
+      }
+    }
+    alternatives.retain(|_, others| !others.is_empty());
+
+    // the longest word wins where words overlap
+    let mut words: Vec<&String> = alternatives.keys().collect();
+    words.sort_by_key(|word| std::cmp::Reverse(word.chars().count()));
+    let pattern = (!words.is_empty()).then(|| {
+      let words: Vec<String> =
+        words.into_iter().map(|w| word_pattern(w)).collect();
+      Regex::new(&format!("(?i){}", words.join("|"))).unwrap()
+    });
+
+    Self {
+      alternatives,
+      pattern,
+    }
+  }
+
+  // Expand the terms and phrases of the query containing synonyms.
+  // Ranges, and terms with a boost, fuzziness or as a prefix, are left
+  // as they are.
+  pub fn expand<'a>(&self, query: &'a str) -> Cow<'a, str> {
+    if self.pattern.is_none() {
+      return Cow::Borrowed(query);
+    }
+
+    UNIT.replace_all(query, |caps: &Captures| {
+      let unit = caps[0].to_string();
+      if caps.name("range").is_some() || caps.name("modifier").is_some() {
+        return unit;
+      }
+      let text = match caps.name("phrase") {
+        Some(phrase) => phrase.as_str(),
+        None if caps["term"].ends_with('*') => return unit,
+        None => &caps["term"],
+      };
+
+      let variants = self.variants(text);
+      if variants.is_empty() {
+        return unit;
+      }
+      let field = caps
+        .name("field")
+        .map_or(String::new(), |field| format!("{}:", field.as_str()));
+      let mut expanded = format!("({unit}");
+      for variant in variants {
+        expanded.push_str(&format!(" OR {field}\"{variant}\"^{SYNONYM_BOOST}"));
+      }
+      expanded.push(')');
+      expanded
+    })
+  }
+
+  // the text with a synonym in place of one of its words, for each
+  // word and synonym
+  fn variants(&self, text: &str) -> Vec<String> {
+    let Some(pattern) = &self.pattern else {
+      return vec![];
+    };
+
+    let mut variants = vec![];
+    for found in pattern.find_iter(text) {
+      let Some(others) = self.alternatives.get(&normalize(found.as_str()))
+      else {
+        continue;
+      };
+      let (before, after) = (&text[..found.start()], &text[found.end()..]);
+      for other in others {
+        variants.push(format!("{before}{other}{after}"));
+      }
+    }
+    variants
+  }
+}
+
+// words compare ignoring case and the spacing between their parts
+fn normalize(word: &str) -> String {
+  word
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ")
+    .to_lowercase()
+}
+
+// A word only matches at the boundaries of ASCII words, so that `db`
+// doesn't match in `mongodb` while `k8s` does in `k8s集群`.
+fn word_pattern(word: &str) -> String {
+  let is_word_char =
+    |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_');
+  let parts: Vec<String> = word.split_whitespace().map(regex::escape).collect();
+
+  let mut pattern = parts.join(r"\s+");
+  if is_word_char(word.chars().next()) {
+    pattern.insert_str(0, r"(?-u:\b)");
+  }
+  if is_word_char(word.chars().last()) {
+    pattern.push_str(r"(?-u:\b)");
+  }
+  pattern
+}
+
+#[cfg(test)]
+mod test {
+  use super::Synonyms;
+
+  #[test]
+  fn test_expand() {
+    let synonyms = Synonyms::new(vec![
+      vec!["k8s".into(), "kubernetes".into(), "容器编排".into()],
+      vec!["db".into(), "database".into()],
+      vec!["container orchestration".into(), "k8s".into()],
+    ]);
+    let expand = |query| synonyms.expand(query).into_owned();
+
+    assert_eq!(
+      expand("K8S deploy"),
+      concat!(
+        r#"(K8S OR "kubernetes"^0.5 OR "容器编排"^0.5"#,
+        r#" OR "container orchestration"^0.5) deploy"#
+      )
+    );
+    assert_eq!(
+      expand("部署容器编排"),
+      r#"(部署容器编排 OR "部署k8s"^0.5 OR "部署kubernetes"^0.5)"#
+    );
+    assert_eq!(
+      expand(r#"+title:db "container  orchestration" -mongodb"#),
+      concat!(
+        r#"+(title:db OR title:"database"^0.5)"#,
+        r#" ("container  orchestration" OR "k8s"^0.5) -mongodb"#
+      )
+    );
+    assert_eq!(
+      expand("db^2 db* updated:[2020-01-01 TO 2021-01-01]"),
+      "db^2 db* updated:[2020-01-01 TO 2021-01-01]"
+    );
+    assert_eq!(Synonyms::default().expand("k8s"), "k8s");
+  }
+}
@@ -6,10 +6,10 @@ use std::{net::SocketAddr, sync::Arc};
 
 use axum::{
   http::StatusCode,
-  response::{IntoResponse, Response},
+  response::{IntoResponse, Redirect, Response},
+  routing::get,
   Extension, Router,
 };
-use tokio::sync::{Mutex, RwLock};
 use tracing::{info, warn};
 
 use crate::{
@@ -21,27 +21,46 @@ use crate::{
 
 pub struct Server {
   bind_addr: SocketAddr,
-  search: Arc<RwLock<Search>>,
-  wiki: Arc<Mutex<Wiki>>,
+  wikis: Vec<HostedWiki>,
 }
 
-type SearchRef = Arc<RwLock<Search>>;
-type WikiRef = Arc<Mutex<Wiki>>;
+/// A wiki served at /w/{name}/, with its own index. The first wiki is
+/// also served at the root.
+#[derive(Clone)]
+pub struct HostedWiki {
+  name: String,
+  search: SearchRef,
+  wiki: WikiRef,
+}
 
-impl Server {
-  pub fn new(bind_addr: SocketAddr, search: Search, wiki: Wiki) -> Self {
-    let search = Arc::new(RwLock::new(search));
-    let wiki = Arc::new(Mutex::new(wiki));
+/// The name of the wiki a request is for.
+#[derive(Clone)]
+struct CurrentWiki(String);
+
+type SearchRef = Arc<Search>;
+type WikiRef = Arc<Wiki>;
+type WikisRef = Arc<Vec<HostedWiki>>;
 
+impl Server {
+  pub fn new(bind_addr: SocketAddr) -> Self {
     Self {
       bind_addr,
-      search,
-      wiki,
+      wikis: vec![],
     }
   }
 
-  pub fn spin_off_reindexer(&self) -> Reindexer {
-    Reindexer::new(self.search.clone(), self.wiki.clone())
+  pub fn add_wiki(
+    &mut self,
+    name: &str,
+    search: Search,
+    wiki: Wiki,
+  ) -> &HostedWiki {
+    self.wikis.push(HostedWiki {
+      name: name.to_string(),
+      search: Arc::new(search),
+      wiki: Arc::new(wiki),
+    });
+    self.wikis.last().unwrap()
   }
 
   pub async fn run(self) -> Result<()> {
@@ -56,12 +75,40 @@ impl Server {
     Ok(())
   }
 
+  fn router(&self) -> Router {
+    let mut router =
+      Router::new().route("/api/search/all", get(handler::search_all));
+
+    for (i, hosted) in self.wikis.iter().enumerate() {
+      if i == 0 {
+        router = router.merge(hosted.router());
+      }
+      let base = format!("/w/{}/", hosted.name);
+      let redirect = Redirect::permanent(&base);
+      router = router
+        .route(
+          base.trim_end_matches('/'),
+          get(move || async move { redirect }),
+        )
+        .nest(&base, hosted.router());
+    }
+
+    router.layer(Extension(Arc::new(self.wikis.clone())))
+  }
+}
+
+impl HostedWiki {
+  pub fn spin_off_reindexer(&self) -> Reindexer {
+    Reindexer::new(self.search.clone(), self.wiki.clone())
+  }
+
   fn router(&self) -> Router {
     Router::new()
       .nest("/api", api::router())
       .merge(front::router())
       .layer(Extension(self.search.clone()))
       .layer(Extension(self.wiki.clone()))
+      .layer(Extension(CurrentWiki(self.name.clone())))
   }
 }
 
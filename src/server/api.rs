@@ -9,5 +9,6 @@ pub fn router() -> Router {
   Router::new()
     .route("/search", get(handler::search))
     .route("/reindex", post(handler::reindex))
+    .route("/reindex/page", post(handler::reindex_page))
   // .route("/morelikethis", get(handler::morelikethis))
 }
@@ -1,4 +1,5 @@
 use axum::{
+  extract::Query,
   routing::{get, post},
   Extension, Form, Router,
 };
@@ -7,8 +8,9 @@ use serde::Deserialize;
 use tantivy::DateTime;
 
 use crate::{
+  reindexer::ReindexMode,
   search::{PageMatchEntry, PageMatchResult, QueryOptions},
-  server::{SearchRef, WikiRef},
+  server::{CurrentWiki, SearchRef, WikiRef, WikisRef},
   util::{Error, Result},
 };
 
@@ -17,6 +19,7 @@ pub fn router() -> Router {
     .route("/search", post(search))
     .route("/reindex", post(reindex))
     .route("/index", get(index_info))
+    .route("/wikis", get(wiki_selector))
 }
 
 #[derive(Deserialize)]
@@ -26,6 +29,8 @@ struct SearchQuery {
   date_before: Option<DateTime>,
   #[serde(deserialize_with = "crate::util::deserialize_date")]
   date_after: Option<DateTime>,
+  #[serde(default, deserialize_with = "crate::util::deserialize_date")]
+  as_of: Option<DateTime>,
   offset: Option<usize>,
 }
 
@@ -34,13 +39,12 @@ async fn search(
   Extension(search): Extension<SearchRef>,
   Form(form): Form<SearchQuery>,
 ) -> Result<Markup> {
-  let search = search.read().await;
-
   let options = QueryOptions {
     offset: form.offset.unwrap_or(0),
     snippet_length: 400,
     date_before: form.date_before,
     date_after: form.date_after,
+    as_of: form.as_of,
     ..Default::default()
   };
   let q = if form.q.trim().is_empty() {
@@ -84,6 +88,12 @@ fn render_result(result: PageMatchResult) -> Markup {
           a href=(entry.url) {
             (PreEscaped(title))
           }
+          @if let Some(section) = &entry.section {
+            span class="section" { " › " (section) }
+          }
+          @if let Some(revision) = entry.revision {
+            span class="section" { " (revision " (revision) ")" }
+          }
         }
         p style="max-width: 40vw;" {
           (PreEscaped(text))
@@ -115,27 +125,28 @@ fn render_result(result: PageMatchResult) -> Markup {
   }
 }
 
+#[derive(Deserialize)]
+struct ReindexQuery {
+  #[serde(default)]
+  full: bool,
+}
+
 async fn reindex(
   Extension(search): Extension<SearchRef>,
   Extension(wiki): Extension<WikiRef>,
+  Query(query): Query<ReindexQuery>,
 ) -> Result<Markup> {
   let start = std::time::Instant::now();
-  let mut wiki = wiki.lock().await;
-  let mut search = search.write().await;
-  let revision = wiki.latest_revision().await?;
+  let mode = ReindexMode::full_if(query.full);
 
-  if !search.requires_reindex(revision) {
+  let reindexed = crate::reindexer::reindex(&wiki, &search, mode);
+  let Some(updated) = reindexed.await? else {
     return Ok(html! {"No reindex required"});
-  }
-
-  let pages = wiki.list_pages().await?;
-  drop(wiki); // drop early to avoid contention from other threads
-
-  search.reindex_pages(pages, revision)?;
+  };
   let page_count = search.page_count()?;
 
   let fragment = html! {
-    "Indexed " (page_count) " pages "
+    "Updated " (updated) " of " (page_count) " pages "
     "in " (format!("{:.2?}", start.elapsed()))
   };
 
@@ -143,10 +154,36 @@ async fn reindex(
 }
 
 async fn index_info(Extension(search): Extension<SearchRef>) -> Result<Markup> {
-  let page_count = search.read().await.page_count()?;
+  let page_count = search.page_count()?;
   let fragment = html! {
     "Indexed " (page_count) " pages"
+    @if let Some(state) = search.index_state() {
+      " (revision " (state.fingerprint.revision) ", "
+      "last reindexed at "
+      (state.reindexed_at.format("%Y-%m-%d %H:%M:%S UTC"))
+      " in " (format!("{:.2?}", state.reindex_duration)) ")"
+    }
   };
 
   Ok(fragment)
 }
+
+// links to the other wikis, if there are any
+async fn wiki_selector(
+  Extension(wikis): Extension<WikisRef>,
+  Extension(current): Extension<CurrentWiki>,
+) -> Markup {
+  html! {
+    @if wikis.len() > 1 {
+      label for="wiki" { "Wiki:" }
+      select id="wiki" onchange="location.href = this.value" {
+        @for hosted in wikis.iter() {
+          option value={ "/w/" (hosted.name) "/" }
+            selected[hosted.name == current.0] {
+            (hosted.name)
+          }
+        }
+      }
+    }
+  }
+}
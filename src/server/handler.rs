@@ -3,27 +3,70 @@ use serde::{Deserialize, Serialize};
 
 use super::*;
 
-pub use reindex::reindex;
-pub use search::search;
+pub use reindex::{reindex, reindex_page};
+pub use search::{search, search_all};
 
 mod reindex {
+  use crate::reindexer::{PageEvent, PageUpdate, ReindexMode};
+
   use super::*;
+  use axum::extract::Query;
+
+  #[derive(Deserialize)]
+  pub struct ReindexRequest {
+    /// rebuild the whole index instead of only the changed pages
+    #[serde(default)]
+    full: bool,
+  }
 
   pub async fn reindex(
+    Query(req): Query<ReindexRequest>,
     Extension(search): Extension<SearchRef>,
     Extension(wiki): Extension<WikiRef>,
   ) -> Result<()> {
-    let mut wiki = wiki.lock().await;
-    let revision = wiki.latest_revision().await?;
-    let pages = wiki.list_pages().await?;
-    drop(wiki);
-    search.write().await.reindex_pages(pages, revision)?;
+    let mode = ReindexMode::full_if(req.full);
+    crate::reindexer::reindex(&wiki, &search, mode).await?;
     Ok(())
   }
+
+  #[derive(Deserialize)]
+  pub struct PageReindexRequest {
+    /// the page id, required for deleted pages
+    id: Option<i64>,
+    /// the full title, e.g. "User talk:Alice", if no id is given
+    title: Option<String>,
+    #[serde(default)]
+    event: PageEvent,
+  }
+
+  #[derive(Serialize)]
+  pub struct PageReindexResponse {
+    id: i64,
+    result: PageUpdate,
+  }
+
+  pub async fn reindex_page(
+    Query(req): Query<PageReindexRequest>,
+    Extension(search): Extension<SearchRef>,
+    Extension(wiki): Extension<WikiRef>,
+  ) -> Result<Json<PageReindexResponse>> {
+    let id = match (req.id, &req.title) {
+      (Some(id), _) => id,
+      (None, Some(title)) => wiki
+        .find_page_id(title)
+        .await?
+        .ok_or_else(|| Error::Generic(format!("no page titled {title}")))?,
+      (None, None) => return Err(Error::Generic("no page given".into())),
+    };
+
+    let result =
+      crate::reindexer::reindex_page(&wiki, &search, id, req.event).await?;
+    Ok(Json(PageReindexResponse { id, result }))
+  }
 }
 
 mod search {
-  use crate::search::QueryOptions;
+  use crate::search::{merge_by_score, PageMatchEntry, QueryOptions};
 
   use super::*;
   use axum::extract::Query;
@@ -56,8 +99,27 @@ mod search {
 
   #[derive(Serialize)]
   struct SearchResult {
+    /// the wiki of the page, when searching all wikis
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wiki: Option<String>,
     title: String,
+    section: Option<String>,
     text: String,
+    url: String,
+    score: f32,
+  }
+
+  impl SearchResult {
+    fn new(entry: &PageMatchEntry, prefix: &str, suffix: &str) -> Self {
+      Self {
+        wiki: None,
+        title: entry.title.highlight(prefix, suffix),
+        section: entry.section.clone(),
+        text: entry.text.highlight(prefix, suffix),
+        url: entry.url.clone(),
+        score: entry.score,
+      }
+    }
   }
 
   #[derive(Serialize)]
@@ -69,21 +131,51 @@ mod search {
     Query(req): Query<SearchRequest>,
     Extension(search): Extension<SearchRef>,
   ) -> Result<Json<SearchResponse>> {
-    let guard = search.read().await;
-    let mut results = vec![];
     let prefix = req.snippet_options.prefix;
     let suffix = req.snippet_options.suffix;
 
-    let result = guard.query(&req.q, &req.options)?;
+    let result = search.query(&req.q, &req.options)?;
+    let results = result
+      .entries
+      .iter()
+      .map(|entry| SearchResult::new(entry, &prefix, &suffix))
+      .collect();
 
-    for entry in result.entries {
-      let title = entry.title.highlight(&prefix, &suffix);
-      let text = entry.text.highlight(&prefix, &suffix);
+    Ok(Json(SearchResponse { results }))
+  }
 
-      let result = SearchResult { title, text };
-      results.push(result);
+  // search every wiki and merge the results by their normalized score
+  pub async fn search_all(
+    Query(req): Query<SearchRequest>,
+    Extension(wikis): Extension<WikisRef>,
+  ) -> Result<Json<SearchResponse>> {
+    let prefix = req.snippet_options.prefix;
+    let suffix = req.snippet_options.suffix;
+
+    // the results of each wiki up to the requested page, as any of
+    // them may end up on it
+    let options = QueryOptions {
+      offset: 0,
+      count: req.options.offset + req.options.count,
+      ..req.options.clone()
+    };
+    let mut lists = vec![];
+    for hosted in wikis.iter() {
+      let result = hosted.search.query(&req.q, &options)?;
+      lists.push((hosted.name.as_str(), result.entries));
     }
 
+    let results = merge_by_score(lists, |entry| entry.score)
+      .into_iter()
+      .skip(req.options.offset)
+      .take(req.options.count)
+      .map(|(wiki, entry, score)| SearchResult {
+        wiki: Some(wiki.to_string()),
+        score,
+        ..SearchResult::new(&entry, &prefix, &suffix)
+      })
+      .collect();
+
     Ok(Json(SearchResponse { results }))
   }
 }
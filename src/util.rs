@@ -22,6 +22,24 @@ pub enum Error {
   #[error("io error: {0}")]
   Io(#[from] std::io::Error),
 
+  #[error("json error: {0}")]
+  Json(#[from] serde_json::Error),
+
+  #[error("xml error: {0}")]
+  Xml(#[from] quick_xml::Error),
+
+  #[error("toml error: {0}")]
+  Toml(#[from] toml::de::Error),
+
+  #[error("http error: {0}")]
+  Http(#[from] reqwest::Error),
+
+  #[error("watch error: {0}")]
+  Notify(#[from] notify::Error),
+
+  #[error("undecodable text: {0}")]
+  UndecodableText(String),
+
   #[error("generic error: `{0}`")]
   Generic(String),
 }
@@ -31,6 +49,29 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 pub type Date = chrono::NaiveDate;
 pub type DateTime = chrono::DateTime<chrono::Utc>;
 
+// number of threads to use by default, one per cpu
+pub fn default_threads() -> usize {
+  std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// Run cpu-bound work on an item, like textifying a page or building
+// its documents, on the blocking thread pool. Items skipped earlier
+// (None) are passed through.
+pub async fn in_background<T, U, F>(
+  item: Option<T>,
+  work: F,
+) -> Result<Option<U>>
+where
+  T: Send + 'static,
+  U: Send + 'static,
+  F: FnOnce(T) -> Result<Option<U>> + Send + 'static,
+{
+  let Some(item) = item else { return Ok(None) };
+  tokio::task::spawn_blocking(move || work(item))
+    .await
+    .map_err(|e| Error::Generic(format!("background task failed: {e}")))?
+}
+
 pub fn parse_date(s: &str) -> Result<tantivy::DateTime> {
   use chrono::NaiveDate;
 
@@ -1,77 +1,708 @@
-use std::{path::Path, time::Duration};
+use std::{str::FromStr, sync::Arc, time::Duration};
 
-use futures_util::StreamExt;
-use sqlx::{pool::PoolOptions, SqlitePool};
+use futures_util::{
+  future,
+  stream::{self, BoxStream},
+  StreamExt, TryStreamExt,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+  mysql::{MySqlPool, MySqlRow},
+  pool::PoolOptions,
+  postgres::{PgPool, PgRow},
+  sqlite::{SqliteConnectOptions, SqlitePool, SqliteRow},
+  Database, FromRow,
+};
 
-use crate::{page::Page, util::Result};
+use tracing::warn;
 
+use crate::{
+  namespace::Namespaces,
+  page::{Page, Revision, WikiTimestamp},
+  util::{self, in_background, Error, Result},
+};
+
+use self::{
+  blob::{undecodable, Blob, StoredText},
+  dialect::{Dialect, Queries, REVISION_BATCH_SIZE},
+};
+
+pub use self::dump::Dump;
+
+mod blob;
+mod dialect;
+mod dump;
 mod textify;
 
+/// A summary of the wiki tables that changes whenever anything
+/// affecting the index happens: edits bump the revision sequence,
+/// deletions land in `archive`, moves and deletions in `logging`,
+/// and category changes (e.g. through a template edit elsewhere)
+/// touch `page` and `categorylinks` without a new revision.
+#[derive(
+  Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::FromRow,
+)]
+pub struct Fingerprint {
+  /// latest revision id
+  pub revision: i64,
+  /// latest archived (deleted) revision id
+  pub archive: i64,
+  /// latest log entry id
+  pub logging: i64,
+  pub page_count: i64,
+  /// most recent `page_touched` of all pages
+  #[sqlx(try_from = "String")]
+  pub touched: WikiTimestamp,
+  pub category_count: i64,
+  /// most recent `cl_timestamp`, kept verbatim
+  pub category_touched: String,
+}
+
+/// A page listed by `Wiki::stream_pages` or `Dump::stream`, or the id
+/// of a listed page that is skipped, as it is empty or its text can't
+/// be decoded.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum StreamedPage {
+  Page(Page),
+  Skipped(i64),
+}
+
+impl StreamedPage {
+  fn prepared(id: i64, page: Option<Page>) -> Self {
+    page.map_or(StreamedPage::Skipped(id), StreamedPage::Page)
+  }
+}
+
 pub struct Wiki {
-  pool: SqlitePool,
-  wiki_base: String,
+  pool: WikiPool,
+  queries: Queries,
+  wiki_base: Arc<str>,
+  namespaces: Arc<Namespaces>,
+  history: bool,
+  /// number of pages or revisions textified at the same time
+  threads: usize,
+}
+
+enum WikiPool {
+  Sqlite(SqlitePool),
+  MySql(MySqlPool),
+  Postgres(PgPool),
+}
+
+// run the same code against the pool of whichever database the wiki
+// lives in
+macro_rules! with_pool {
+  ($pool:expr, $p:ident => $body:expr) => {
+    match $pool {
+      WikiPool::Sqlite($p) => $body,
+      WikiPool::MySql($p) => $body,
+      WikiPool::Postgres($p) => $body,
+    }
+  };
+}
+
+// a page or revision with the columns locating its text
+#[derive(sqlx::FromRow)]
+struct WithText<T> {
+  #[sqlx(flatten)]
+  item: T,
+  #[sqlx(flatten)]
+  stored: StoredText,
+}
+
+enum Param {
+  Int(i64),
+  Text(String),
+}
+
+// a page or revision whose text is decoded separately
+trait Stored {
+  fn set_text(&mut self, text: String);
+  // for logging
+  fn describe(&self) -> String;
+}
+
+impl Stored for Page {
+  fn set_text(&mut self, text: String) {
+    self.text = text;
+  }
+
+  fn describe(&self) -> String {
+    format!("page {} ({})", self.title, self.id)
+  }
+}
+
+impl Stored for Revision {
+  fn set_text(&mut self, text: String) {
+    self.text = text;
+  }
+
+  fn describe(&self) -> String {
+    format!("revision {}", self.id)
+  }
 }
 
 impl Wiki {
+  // `database_url` is a sqlite://, mysql:// (or mariadb://) or
+  // postgres:// url. `table_prefix` is the `$wgDBprefix` of the wiki.
   pub async fn new(
-    sqlite_path: &Path,
+    database_url: &str,
+    table_prefix: &str,
     wiki_base: impl Into<String>,
   ) -> Result<Self> {
-    let options = sqlx::sqlite::SqliteConnectOptions::new()
-      .filename(sqlite_path)
-      .read_only(true);
-
-    let pool_options =
-      PoolOptions::new().idle_timeout(Some(Duration::from_secs(5 * 60)));
-    let pool = pool_options.connect_lazy_with(options);
-
-    let wiki_base = wiki_base.into();
-
-    Ok(Self { pool, wiki_base })
-  }
-
-  pub async fn list_pages(&mut self) -> Result<Vec<Page>> {
-    const SQL: &str = concat!(
-      "SELECT",
-      "    page.page_id as id, ",
-      "    replace(page.page_title, '_', ' ') as title, ",
-      "    text.old_text as text, ",
-      "    page.page_touched as updated, ",
-      "    page.page_namespace as namespace, ",
-      "    (SELECT GROUP_CONCAT(categorylinks.cl_to, '<|||>')
-            FROM categorylinks
-            WHERE categorylinks.cl_from = page.page_id) as categories ",
-      "FROM page ",
-      "LEFT JOIN slots ON page.page_latest = slots.slot_revision_id ",
-      "LEFT JOIN content ON slots.slot_content_id = content.content_id ",
-      "LEFT JOIN text ON ltrim(content.content_address, 'tt:') = text.old_id",
-    );
+    let dialect = Dialect::from_url(database_url)?;
+    let pool = match dialect {
+      Dialect::Sqlite => {
+        let options =
+          SqliteConnectOptions::from_str(database_url)?.read_only(true);
+        WikiPool::Sqlite(pool_options().connect_lazy_with(options))
+      }
+      Dialect::MySql => {
+        WikiPool::MySql(pool_options().connect_lazy(database_url)?)
+      }
+      Dialect::Postgres => {
+        WikiPool::Postgres(pool_options().connect_lazy(database_url)?)
+      }
+    };
 
-    let mut pages = vec![];
-    let mut stream = sqlx::query_as::<_, Page>(SQL).fetch(&self.pool);
+    let queries = Queries::new(dialect, table_prefix);
+    let wiki_base = wiki_base.into().into();
 
-    while let Some(val) = stream.next().await {
-      let mut page = val?;
-      if page.text.trim().is_empty() {
-        continue;
+    Ok(Self {
+      pool,
+      queries,
+      wiki_base,
+      namespaces: Arc::new(Namespaces::default()),
+      history: false,
+      threads: util::default_threads(),
+    })
+  }
+
+  // use the namespace names configured in the wiki instead of only
+  // the built-in ones
+  pub fn with_namespaces(mut self, namespaces: Namespaces) -> Self {
+    self.namespaces = Arc::new(namespaces);
+    self
+  }
+
+  // also list past revisions for indexing
+  pub fn with_history(mut self, history: bool) -> Self {
+    self.history = history;
+    self
+  }
+
+  pub fn with_threads(mut self, threads: usize) -> Self {
+    self.threads = threads.max(1);
+    self
+  }
+
+  // Stream all pages, or only the pages changed since the given
+  // fingerprint: those whose latest revision or touch time moved past
+  // it, or which got new or updated redirects. Pages touched within
+  // the same second as the fingerprint are listed again to be on the
+  // safe side.
+  //
+  // Rows are read as the stream is consumed and textified on the
+  // blocking thread pool, a few pages ahead of the consumer. Pages
+  // that are empty or whose text can't be decoded are yielded as
+  // skipped, so that their previous documents can be removed.
+  pub fn stream_pages(
+    &self,
+    since: Option<&Fingerprint>,
+  ) -> BoxStream<'_, Result<StreamedPage>> {
+    let rows = match since {
+      None => self.fetch(&self.queries.list_pages, vec![]),
+      Some(fingerprint) => {
+        let touched = fingerprint.touched.to_wiki_format();
+        let params = vec![
+          Param::Int(fingerprint.revision),
+          Param::Text(touched.clone()),
+          Param::Int(fingerprint.revision),
+          Param::Text(touched),
+        ];
+        self.fetch(&self.queries.list_pages_since, params)
       }
+    };
+
+    self.prepare_pages(rows)
+  }
 
-      page.fill_url(&self.wiki_base);
+  // The page with the given id, or None if there is no such page, it
+  // is a redirect, or it is skipped like in `stream_pages`.
+  pub async fn get_page(&self, id: i64) -> Result<Option<Page>> {
+    let rows = self.fetch(&self.queries.get_page, vec![Param::Int(id)]);
+    match self.prepare_pages(rows).try_next().await? {
+      Some(StreamedPage::Page(page)) => Ok(Some(page)),
+      Some(StreamedPage::Skipped(_)) | None => Ok(None),
+    }
+  }
+
+  // the id of the page with the title, e.g. "User talk:Alice"
+  pub async fn find_page_id(&self, title: &str) -> Result<Option<i64>> {
+    let (namespace, title) = self.namespaces.parse_title(title);
+    let params = vec![Param::Int(namespace.id.into()), Param::Text(title)];
+    let mut rows = self.fetch::<(i64,)>(&self.queries.find_page_id, params);
+    let id = rows.try_next().await?.map(|(id,)| id);
+    Ok(id)
+  }
 
-      page.text = textify::textify(&page.text);
-      pages.push(page);
+  fn prepare_pages<'a>(
+    &'a self,
+    rows: BoxStream<'a, sqlx::Result<WithText<Page>>>,
+  ) -> BoxStream<'a, Result<StreamedPage>> {
+    rows
+      .map_err(Error::from)
+      .and_then(move |row| async move {
+        let id = row.item.id;
+        Ok((id, self.decode(row).await?))
+      })
+      .map_ok(move |(id, page)| {
+        let wiki_base = self.wiki_base.clone();
+        let namespaces = self.namespaces.clone();
+        async move {
+          let page = in_background(page, move |page| {
+            Ok(prepare_page(page, &wiki_base, &namespaces))
+          })
+          .await?;
+          Ok(StreamedPage::prepared(id, page))
+        }
+      })
+      .try_buffered(self.threads)
+      .boxed()
+  }
+
+  // Stream all revisions of all pages, or only of the given pages,
+  // ordered by page and time. Nothing is listed unless history mode is
+  // enabled. Like pages, skipped revisions are yielded as None.
+  pub fn stream_revisions(
+    &self,
+    page_ids: Option<&[i64]>,
+  ) -> BoxStream<'_, Result<Option<Revision>>> {
+    if !self.history {
+      return stream::empty().boxed();
     }
 
-    Ok(pages)
+    let rows = match page_ids {
+      None => self.fetch(&self.queries.list_all_revisions, vec![]),
+      // all revisions of a page are in the same batch
+      Some(ids) => {
+        let batches: Vec<Vec<Param>> =
+          ids.chunks(REVISION_BATCH_SIZE).map(batch_params).collect();
+        stream::iter(batches)
+          .flat_map(move |params| {
+            self.fetch(&self.queries.list_page_revisions, params)
+          })
+          .boxed()
+      }
+    };
+
+    with_valid_until(rows)
+      .and_then(move |row| self.decode(row))
+      .map_ok(move |revision| {
+        let wiki_base = self.wiki_base.clone();
+        let namespaces = self.namespaces.clone();
+        in_background(revision, move |revision| {
+          Ok(prepare_revision(revision, &wiki_base, &namespaces))
+        })
+      })
+      .try_buffered(self.threads)
+      .boxed()
   }
 
-  // return a number that increases when any update is made that
+  // the page or revision with its text, or None if the text can't be
+  // decoded
+  async fn decode<T: Stored>(&self, row: WithText<T>) -> Result<Option<T>> {
+    let WithText { mut item, stored } = row;
+
+    match self.load_text(stored).await {
+      Ok(text) => {
+        item.set_text(text);
+        Ok(Some(item))
+      }
+      Err(Error::UndecodableText(reason)) => {
+        warn!("skipping {}: {}", item.describe(), reason);
+        Ok(None)
+      }
+      Err(e) => Err(e),
+    }
+  }
+
+  // decode the text as stored by MediaWiki, failing with
+  // `Error::UndecodableText` if it can't be read
+  async fn load_text(&self, stored: StoredText) -> Result<String> {
+    let (data, flags) = match stored.into_blob()? {
+      Blob::Data(data, flags) => (data, flags),
+      Blob::External(id, flags) => (self.external_blob(id).await?, flags),
+    };
+    blob::decode(data, &flags)
+  }
+
+  async fn external_blob(&self, id: i64) -> Result<Vec<u8>> {
+    let params = vec![Param::Int(id)];
+    let mut rows =
+      self.fetch::<(Vec<u8>,)>(&self.queries.external_blob, params);
+
+    match rows.next().await {
+      Some(Ok((data,))) => Ok(data),
+      // a failing database fails the indexing rather than skipping pages
+      Some(Err(e)) => Err(e.into()),
+      None => Err(undecodable(format!("external blob {id} not found"))),
+    }
+  }
+
+  pub async fn list_page_ids(&self) -> Result<Vec<i64>> {
+    let ids = self
+      .fetch::<(i64,)>(&self.queries.list_page_ids, vec![])
+      .map_ok(|(id,)| id)
+      .try_collect()
+      .await?;
+    Ok(ids)
+  }
+
+  // return a fingerprint that changes when any update is made that
   // requires reindexing.
-  pub async fn latest_revision(&self) -> Result<u32> {
-    // A O(1) way to get the primary key sequence value, c.f.
-    // https://stackoverflow.com/a/2217015
-    const SQL: &str = "SELECT seq FROM sqlite_sequence WHERE name = 'revision'";
-    let val: (u32,) = sqlx::query_as(SQL).fetch_one(&self.pool).await?;
-    Ok(val.0)
+  pub async fn fingerprint(&self) -> Result<Fingerprint> {
+    let mut rows = self.fetch(&self.queries.fingerprint, vec![]);
+    let fingerprint = rows.next().await.ok_or(sqlx::Error::RowNotFound)??;
+    Ok(fingerprint)
+  }
+
+  fn fetch<'e, T>(
+    &'e self,
+    sql: &'e str,
+    params: Vec<Param>,
+  ) -> BoxStream<'e, sqlx::Result<T>>
+  where
+    T: Send + Unpin + 'e,
+    T: for<'r> FromRow<'r, SqliteRow>,
+    T: for<'r> FromRow<'r, MySqlRow>,
+    T: for<'r> FromRow<'r, PgRow>,
+  {
+    with_pool!(&self.pool, pool => {
+      let mut query = sqlx::query_as(sql);
+      for param in params {
+        query = match param {
+          Param::Int(i) => query.bind(i),
+          Param::Text(s) => query.bind(s),
+        };
+      }
+      query.fetch(pool)
+    })
+  }
+}
+
+// turn the wikitext of a page into indexable text, or None if the page
+// is empty
+fn prepare_page(
+  mut page: Page,
+  wiki_base: &str,
+  namespaces: &Namespaces,
+) -> Option<Page> {
+  if page.text.trim().is_empty() {
+    return None;
+  }
+
+  page.namespace = namespaces.get(page.namespace.id);
+  page.fill_url(wiki_base);
+
+  page.sections = textify::sections(&page.text);
+  page.text = textify::join_sections(&page.sections);
+  Some(page)
+}
+
+fn prepare_revision(
+  mut rev: Revision,
+  wiki_base: &str,
+  namespaces: &Namespaces,
+) -> Option<Revision> {
+  if rev.text.trim().is_empty() {
+    return None;
+  }
+
+  rev.namespace = namespaces.get(rev.namespace.id);
+  rev.fill_url(wiki_base);
+  rev.text = textify::textify(&rev.text);
+  Some(rev)
+}
+
+// Each revision is valid until the next revision of the same page, as
+// rows come ordered by page and time, so a revision is only released
+// once the next row is read.
+fn with_valid_until<'a>(
+  rows: BoxStream<'a, sqlx::Result<WithText<Revision>>>,
+) -> BoxStream<'a, Result<WithText<Revision>>> {
+  let rows = rows.map(Some).chain(stream::once(future::ready(None)));
+
+  rows
+    .scan(None, |held: &mut Option<WithText<Revision>>, row| {
+      let released = match row {
+        Some(Err(e)) => Some(Err(e.into())),
+        Some(Ok(row)) => {
+          let mut previous = held.replace(row);
+          if let (Some(previous), Some(next)) = (&mut previous, &*held) {
+            if previous.item.page_id == next.item.page_id {
+              previous.item.valid_until = Some(next.item.timestamp.clone());
+            }
+          }
+          previous.map(Ok)
+        }
+        None => held.take().map(Ok),
+      };
+      future::ready(Some(released))
+    })
+    .filter_map(future::ready)
+    .boxed()
+}
+
+// the ids of a batch of pages, repeating the last id to fill all
+// parameters of the query
+fn batch_params(ids: &[i64]) -> Vec<Param> {
+  let last = ids.last().copied().unwrap_or_default();
+  let mut params: Vec<Param> = ids.iter().map(|id| Param::Int(*id)).collect();
+  params.resize_with(REVISION_BATCH_SIZE, || Param::Int(last));
+  params
+}
+
+fn pool_options<DB: Database>() -> PoolOptions<DB> {
+  PoolOptions::new().idle_timeout(Some(Duration::from_secs(5 * 60)))
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::{Path, PathBuf};
+
+  use sqlx::{
+    mysql::MySqlConnection,
+    postgres::PgConnection,
+    sqlite::{SqliteConnectOptions, SqliteConnection},
+    Connection, Executor,
+  };
+
+  use futures_util::{stream::BoxStream, TryStreamExt};
+
+  use super::{StreamedPage, Wiki};
+  use crate::{page::Page, util::Result};
+
+  const FIXTURE: &str = include_str!("wiki/fixtures/wiki.sql");
+  const MYSQL_FIXTURE: &str = include_str!("wiki/fixtures/wiki.mysql.sql");
+  const POSTGRES_FIXTURE: &str =
+    include_str!("wiki/fixtures/wiki.postgres.sql");
+  const PREFIX: &str = "mw_";
+
+  // a fresh SQLite database loaded with the fixture
+  async fn fixture_db(name: &str) -> PathBuf {
+    let file = format!("wiki-search-{}-{}.sqlite", name, std::process::id());
+    let path = std::env::temp_dir().join(file);
+    let _ = std::fs::remove_file(&path);
+
+    let sql = FIXTURE.replace("/*_*/", PREFIX);
+    let mut conn = connect(&path).await;
+    conn.execute(sql.as_str()).await.unwrap();
+    conn.close().await.unwrap();
+    path
+  }
+
+  async fn connect(path: &Path) -> SqliteConnection {
+    let options = SqliteConnectOptions::new()
+      .filename(path)
+      .create_if_missing(true);
+    SqliteConnection::connect_with(&options).await.unwrap()
+  }
+
+  // the pages that were not skipped, and the ids of those that were
+  async fn listed_pages(
+    stream: BoxStream<'_, Result<StreamedPage>>,
+  ) -> (Vec<Page>, Vec<i64>) {
+    let mut listed = (vec![], vec![]);
+    let pages: Vec<_> = stream.try_collect().await.unwrap();
+    for page in pages {
+      match page {
+        StreamedPage::Page(page) => listed.0.push(page),
+        StreamedPage::Skipped(id) => listed.1.push(id),
+      }
+    }
+    listed
+  }
+
+  // the revisions that were not skipped
+  async fn listed<T>(stream: BoxStream<'_, Result<Option<T>>>) -> Vec<T> {
+    let items: Vec<_> = stream.try_collect().await.unwrap();
+    items.into_iter().flatten().collect()
+  }
+
+  async fn fixture_wiki(path: &Path) -> Wiki {
+    wiki_at(&format!("sqlite://{}", path.display())).await
+  }
+
+  async fn wiki_at(url: &str) -> Wiki {
+    let wiki = Wiki::new(url, PREFIX, "https://wiki.example/").await;
+    wiki.unwrap().with_history(true)
+  }
+
+  #[tokio::test]
+  async fn test_list_pages() {
+    let path = fixture_db("list-pages").await;
+    check_fixture(&fixture_wiki(&path).await).await;
+    let _ = std::fs::remove_file(&path);
+  }
+
+  // The url of a scratch MySQL or Postgres database to load the
+  // fixture into, from the environment variable. These tests are
+  // ignored by default, as they need a database server, see README.
+  fn server_url(var: &str) -> Option<String> {
+    let url = std::env::var(var).ok();
+    if url.is_none() {
+      eprintln!("{var} is not set, skipping");
+    }
+    url
+  }
+
+  #[tokio::test]
+  #[ignore = "needs a MySQL database in WIKI_SEARCH_TEST_MYSQL_URL"]
+  async fn test_mysql() {
+    let Some(url) = server_url("WIKI_SEARCH_TEST_MYSQL_URL") else {
+      return;
+    };
+    let sql = MYSQL_FIXTURE.replace("/*_*/", PREFIX);
+    let mut conn = MySqlConnection::connect(&url).await.unwrap();
+    conn.execute(sql.as_str()).await.unwrap();
+    conn.close().await.unwrap();
+
+    check_fixture(&wiki_at(&url).await).await;
+  }
+
+  #[tokio::test]
+  #[ignore = "needs a Postgres database in WIKI_SEARCH_TEST_POSTGRES_URL"]
+  async fn test_postgres() {
+    let Some(url) = server_url("WIKI_SEARCH_TEST_POSTGRES_URL") else {
+      return;
+    };
+    let sql = POSTGRES_FIXTURE.replace("/*_*/", PREFIX);
+    let mut conn = PgConnection::connect(&url).await.unwrap();
+    conn.execute(sql.as_str()).await.unwrap();
+    conn.close().await.unwrap();
+
+    check_fixture(&wiki_at(&url).await).await;
+  }
+
+  // run all queries on the fixture, as loaded into any database
+  async fn check_fixture(wiki: &Wiki) {
+    let (mut pages, skipped) = listed_pages(wiki.stream_pages(None)).await;
+    pages.sort_by_key(|p| p.id);
+    // the page whose text can't be decoded
+    assert_eq!(skipped, [7]);
+    let titles: Vec<_> = pages.iter().map(|p| p.title.as_str()).collect();
+    let expected = [
+      "Jan 2, 2023",
+      "Rust",
+      "Compressed",
+      "External",
+      "Legacy external",
+    ];
+    assert_eq!(titles, expected);
+
+    let diary = &pages[0];
+    assert!(diary.title_date.0.is_some());
+    assert_eq!(diary.text, "Went hiking in the mountains.");
+    assert_eq!(diary.categories.iter().collect::<Vec<_>>(), ["Diary"]);
+    assert_eq!(diary.url, "https://wiki.example/Jan 2, 2023");
+    assert_eq!(diary.created.to_wiki_format(), "20230102080000");
+    assert_eq!(diary.creator, "Alice");
+    assert_eq!(diary.last_editor, "Bob");
+    assert_eq!(diary.revision_count, 2);
+
+    let rust = &pages[1];
+    assert_eq!(rust.aliases.iter().collect::<Vec<_>>(), ["Rust (language)"]);
+    assert_eq!(rust.sections.len(), 2);
+    assert_eq!(rust.sections[1].anchor, "History");
+
+    // Project and a custom namespace unknown without configuration
+    assert_eq!(pages[2].url, "https://wiki.example/Project:Compressed");
+    assert_eq!(pages[3].url, "https://wiki.example/Special:Redirect/page/5");
+
+    assert_eq!(pages[2].text, "Compressed text about Zürich.");
+    assert_eq!(pages[3].text, "Text in an external store.");
+    assert_eq!(pages[4].text, "Text in an old external store.");
+
+    let mut ids = wiki.list_page_ids().await.unwrap();
+    ids.sort();
+    assert_eq!(ids, [1, 2, 4, 5, 6, 7]);
+
+    let page = wiki.get_page(4).await.unwrap().unwrap();
+    assert_eq!(page.text, "Compressed text about Zürich.");
+    // redirects and pages whose text can't be decoded
+    assert!(wiki.get_page(3).await.unwrap().is_none());
+    assert!(wiki.get_page(7).await.unwrap().is_none());
+
+    let find = |title| wiki.find_page_id(title);
+    assert_eq!(find("project:compressed").await.unwrap(), Some(4));
+    assert_eq!(find("Rust (language)").await.unwrap(), Some(3));
+    assert_eq!(find("Missing").await.unwrap(), None);
+
+    let revisions = listed(wiki.stream_revisions(Some(&[1]))).await;
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[0].actor, "Alice");
+    assert_eq!(revisions[0].text, "Went hiking.");
+    assert_eq!(
+      revisions[0].valid_until,
+      Some(revisions[1].timestamp.clone())
+    );
+    assert_eq!(revisions[1].valid_until, None);
+
+    // all but the redirect's, with the undecodable one skipped
+    let revisions = listed(wiki.stream_revisions(None)).await;
+    assert_eq!(revisions.len(), 6);
+
+    let fingerprint = wiki.fingerprint().await.unwrap();
+    assert_eq!(fingerprint.revision, 8);
+    assert_eq!(fingerprint.logging, 1);
+    assert_eq!(fingerprint.page_count, 7);
+    assert_eq!(fingerprint.touched.to_wiki_format(), "20230105090000");
+    assert_eq!(fingerprint.category_count, 2);
+
+    // only the most recently touched page is listed again
+    let (pages, _) = listed_pages(wiki.stream_pages(Some(&fingerprint))).await;
+    let ids: Vec<_> = pages.iter().map(|p| p.id).collect();
+    assert_eq!(ids, [2]);
+  }
+
+  #[tokio::test]
+  async fn test_list_pages_since() {
+    let path = fixture_db("list-pages-since").await;
+    let wiki = fixture_wiki(&path).await;
+    let fingerprint = wiki.fingerprint().await.unwrap();
+
+    let mut conn = connect(&path).await;
+    let edit = "
+      INSERT INTO mw_text VALUES (8, 'Went hiking again.', 'utf-8');
+      INSERT INTO mw_content VALUES (9, 'tt:8');
+      INSERT INTO mw_revision VALUES (9, 1, 1, '20230106080000');
+      INSERT INTO mw_slots VALUES (9, 1, 9);
+      UPDATE mw_page SET page_latest = 9, page_touched = '20230106080000'
+        WHERE page_id = 1;";
+    conn.execute(edit).await.unwrap();
+    conn.close().await.unwrap();
+
+    let updated = wiki.fingerprint().await.unwrap();
+    assert_ne!(updated, fingerprint);
+    assert_eq!(updated.revision, 9);
+
+    let (pages, _) = listed_pages(wiki.stream_pages(Some(&fingerprint))).await;
+    let diary = pages.iter().find(|p| p.id == 1).unwrap();
+    assert_eq!(diary.text, "Went hiking again.");
+
+    // a page blanked since is listed as skipped, to be removed
+    let mut conn = connect(&path).await;
+    let blank = "UPDATE mw_text SET old_text = '' WHERE old_id = 8;";
+    conn.execute(blank).await.unwrap();
+    conn.close().await.unwrap();
+
+    let (pages, skipped) =
+      listed_pages(wiki.stream_pages(Some(&fingerprint))).await;
+    assert!(pages.iter().all(|p| p.id != 1));
+    assert_eq!(skipped, [1]);
+
+    let _ = std::fs::remove_file(&path);
   }
 }
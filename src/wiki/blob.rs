@@ -0,0 +1,195 @@
+// Decoding of page text as MediaWiki stores it. The content address
+// of a revision points either to a row of the `text` table ("tt:") or
+// to an external store ("es:"), and the flags of the row tell whether
+// the text is compressed, in a legacy encoding, serialized, or again
+// only a pointer to an external store.
+
+use std::io::Read;
+
+use crate::util::{Error, Result};
+
+/// The columns describing where and how the text of a revision is
+/// stored.
+#[derive(Clone, Debug, Default, sqlx::FromRow)]
+pub struct StoredText {
+  /// e.g. "tt:123" or "es:DB://cluster1/45?flags=utf-8,gzip"
+  pub content_address: Option<String>,
+  /// the row of the text table, for "tt:" addresses
+  pub old_text: Option<Vec<u8>>,
+  pub old_flags: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Blob {
+  /// the stored bytes
+  Data(Vec<u8>, Flags),
+  /// the id of a row of the external store's `blobs` table
+  External(i64, Flags),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Flags(Vec<String>);
+
+pub fn undecodable(reason: impl Into<String>) -> Error {
+  Error::UndecodableText(reason.into())
+}
+
+impl StoredText {
+  pub fn into_blob(self) -> Result<Blob> {
+    let address = self.content_address.unwrap_or_default();
+
+    if let Some(url) = address.strip_prefix("es:") {
+      let (url, flags) = match url.split_once("?flags=") {
+        Some((url, flags)) => (url, flags.replace("%2C", ",")),
+        None => (url, String::new()),
+      };
+      return Ok(Blob::External(external_blob_id(url)?, Flags::new(&flags)));
+    }
+
+    if !address.starts_with("tt:") {
+      return Err(undecodable(format!("unknown content address {address:?}")));
+    }
+
+    let data = self
+      .old_text
+      .ok_or_else(|| undecodable(format!("no text row for {address}")))?;
+    let mut flags = Flags::new(&self.old_flags.unwrap_or_default());
+
+    // the text row only holds the url of the text in an external store
+    if flags.remove("external") {
+      let url = String::from_utf8_lossy(&data);
+      return Ok(Blob::External(external_blob_id(&url)?, flags));
+    }
+
+    Ok(Blob::Data(data, flags))
+  }
+}
+
+// Only "DB://cluster/id" urls are supported, and only if the `blobs`
+// table of the cluster is in the wiki's own database. Urls with an
+// item id ("DB://cluster/id/item") point into serialized PHP objects.
+fn external_blob_id(url: &str) -> Result<i64> {
+  let parts: Vec<&str> = match url.strip_prefix("DB://") {
+    Some(path) => path.split('/').collect(),
+    None => vec![],
+  };
+
+  match parts.as_slice() {
+    [_cluster, id] => id
+      .parse()
+      .map_err(|_| undecodable(format!("invalid external store url {url}"))),
+    _ => Err(undecodable(format!("unsupported external store url {url}"))),
+  }
+}
+
+impl Flags {
+  pub fn new(flags: &str) -> Self {
+    let flags = flags
+      .split(',')
+      .map(|flag| flag.trim().to_string())
+      .filter(|flag| !flag.is_empty())
+      .collect();
+    Self(flags)
+  }
+
+  fn contains(&self, flag: &str) -> bool {
+    self.0.iter().any(|f| f == flag)
+  }
+
+  fn remove(&mut self, flag: &str) -> bool {
+    let len = self.0.len();
+    self.0.retain(|f| f != flag);
+    self.0.len() != len
+  }
+}
+
+pub fn decode(data: Vec<u8>, flags: &Flags) -> Result<String> {
+  if flags.contains("object") {
+    return Err(undecodable("text is a serialized PHP object"));
+  }
+  if flags.contains("error") {
+    return Err(undecodable("text is flagged as an error"));
+  }
+
+  // $wgCompressRevisions uses gzdeflate(), i.e. raw deflate without
+  // the gzip header
+  let data = if flags.contains("gzip") {
+    let mut inflated = vec![];
+    flate2::read::DeflateDecoder::new(data.as_slice())
+      .read_to_end(&mut inflated)
+      .map_err(|e| undecodable(format!("cannot inflate text: {e}")))?;
+    inflated
+  } else {
+    data
+  };
+
+  match String::from_utf8(data) {
+    Ok(text) => Ok(text),
+    // Without the utf-8 flag, the text may be in the $wgLegacyEncoding
+    // of old wikis, usually windows-1252, which is close enough to
+    // latin-1 to be indexed.
+    Err(e) if !flags.contains("utf-8") => {
+      Ok(e.into_bytes().into_iter().map(char::from).collect())
+    }
+    Err(_) => Err(undecodable("text is not valid utf-8")),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::io::Write;
+
+  use super::{decode, Blob, Flags, StoredText};
+
+  fn stored(address: &str, text: &[u8], flags: &str) -> StoredText {
+    StoredText {
+      content_address: Some(address.into()),
+      old_text: Some(text.into()),
+      old_flags: Some(flags.into()),
+    }
+  }
+
+  fn decode_stored(stored: StoredText) -> Option<String> {
+    match stored.into_blob().ok()? {
+      Blob::Data(data, flags) => decode(data, &flags).ok(),
+      Blob::External(..) => None,
+    }
+  }
+
+  #[test]
+  fn test_decode_text() {
+    let mut encoder = flate2::write::DeflateEncoder::new(
+      vec![],
+      flate2::Compression::default(),
+    );
+    encoder.write_all("Grüße".as_bytes()).unwrap();
+    let deflated = encoder.finish().unwrap();
+
+    let decoded =
+      |address, text: &[u8], flags| decode_stored(stored(address, text, flags));
+    assert_eq!(decoded("tt:1", b"plain", ""), Some("plain".into()));
+    assert_eq!(
+      decoded("tt:1", &deflated, "utf-8,gzip"),
+      Some("Grüße".into())
+    );
+    assert_eq!(decoded("tt:1", b"Gr\xfc\xdfe", ""), Some("Grüße".into()));
+    assert_eq!(decoded("tt:1", b"Gr\xfc\xdfe", "utf-8"), None);
+    assert_eq!(decoded("tt:1", b"O:15:\"HistoryBlob\"", "object"), None);
+    assert_eq!(decoded("bad:1", b"", ""), None);
+  }
+
+  #[test]
+  fn test_external_blob() {
+    let flags = Flags::new("utf-8,gzip");
+
+    let address = "es:DB://cluster1/45?flags=utf-8%2Cgzip";
+    let blob = stored(address, b"", "").into_blob().unwrap();
+    assert_eq!(blob, Blob::External(45, flags.clone()));
+
+    let blob = stored("tt:1", b"DB://cluster1/45", "external,utf-8,gzip");
+    assert_eq!(blob.into_blob().unwrap(), Blob::External(45, flags));
+
+    let blob = stored("tt:1", b"DB://cluster1/45/3", "external,object");
+    assert!(blob.into_blob().is_err());
+  }
+}
@@ -0,0 +1,438 @@
+// SQL for the databases MediaWiki runs on. The queries are the same
+// for every database, but they differ in how binary strings,
+// timestamps, string aggregation and bind parameters are spelled, and
+// in the names of the tables.
+
+use crate::util::{Error, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+  Sqlite,
+  /// MySQL and MariaDB
+  MySql,
+  Postgres,
+}
+
+// separator of the lists aggregated by the database, c.f. `Categories`
+const LIST_SEPARATOR: &str = "<|||>";
+
+// number of pages whose revisions are listed in one query, well below
+// the bind parameter limits of all the databases. Smaller batches
+// repeat the last page id to fill up the parameters.
+pub const REVISION_BATCH_SIZE: usize = 500;
+
+impl Dialect {
+  pub fn from_url(url: &str) -> Result<Self> {
+    let scheme = url.split(':').next().unwrap_or_default();
+    match scheme {
+      "sqlite" => Ok(Dialect::Sqlite),
+      "mysql" | "mariadb" => Ok(Dialect::MySql),
+      "postgres" | "postgresql" => Ok(Dialect::Postgres),
+      _ => Err(Error::Generic(format!("unsupported database url: {url}"))),
+    }
+  }
+
+  // the n-th bind parameter, counting from 1
+  fn param(self, n: usize) -> String {
+    match self {
+      Dialect::Postgres => format!("${n}"),
+      _ => "?".into(),
+    }
+  }
+
+  // MySQL stores titles, names and flags as binary strings
+  fn text(self, expr: &str) -> String {
+    match self {
+      Dialect::MySql => format!("CAST({expr} AS CHAR)"),
+      _ => expr.into(),
+    }
+  }
+
+  // stored text as bytes, as it may be compressed
+  fn bytes(self, expr: &str) -> String {
+    match self {
+      Dialect::Postgres => format!("convert_to({expr}, 'UTF8')"),
+      _ => expr.into(),
+    }
+  }
+
+  // ids are unsigned on MySQL and 32-bit on Postgres
+  fn int(self, expr: &str) -> String {
+    match self {
+      Dialect::Sqlite => expr.into(),
+      Dialect::MySql => format!("CAST({expr} AS SIGNED)"),
+      Dialect::Postgres => format!("CAST({expr} AS BIGINT)"),
+    }
+  }
+
+  // timestamps in MediaWiki's 14-digit format, which Postgres
+  // replaces with a proper timestamp type
+  fn timestamp(self, expr: &str) -> String {
+    match self {
+      Dialect::Postgres => {
+        format!("to_char({expr} AT TIME ZONE 'UTC', 'YYYYMMDDHH24MISS')")
+      }
+      _ => self.text(expr),
+    }
+  }
+
+  fn group_concat(self, expr: &str) -> String {
+    match self {
+      Dialect::Sqlite => format!("GROUP_CONCAT({expr}, '{LIST_SEPARATOR}')"),
+      Dialect::MySql => {
+        format!("GROUP_CONCAT({expr} SEPARATOR '{LIST_SEPARATOR}')")
+      }
+      Dialect::Postgres => format!("string_agg({expr}, '{LIST_SEPARATOR}')"),
+    }
+  }
+
+  // the id in the text table from a content address like "tt:123"
+  fn text_id(self, address: &str) -> String {
+    let int = match self {
+      Dialect::Sqlite => return format!("ltrim({address}, 'tt:')"),
+      Dialect::MySql => "UNSIGNED",
+      Dialect::Postgres => "INTEGER",
+    };
+    format!(
+      "CASE WHEN {address} LIKE 'tt:%' \
+         THEN CAST(substr({address}, 4) AS {int}) END"
+    )
+  }
+}
+
+/// The queries on a wiki database, rendered for its dialect and
+/// table prefix (`$wgDBprefix`).
+#[derive(Clone, Debug)]
+pub struct Queries {
+  dialect: Dialect,
+  prefix: String,
+  pub list_pages: String,
+  pub list_pages_since: String,
+  pub list_page_ids: String,
+  /// the page with the given id
+  pub get_page: String,
+  /// the id of the page with the given namespace and title
+  pub find_page_id: String,
+  pub list_all_revisions: String,
+  /// the revisions of `REVISION_BATCH_SIZE` pages
+  pub list_page_revisions: String,
+  pub fingerprint: String,
+  pub external_blob: String,
+}
+
+impl Queries {
+  pub fn new(dialect: Dialect, prefix: &str) -> Self {
+    let mut queries = Self {
+      dialect,
+      prefix: prefix.into(),
+      list_pages: String::new(),
+      list_pages_since: String::new(),
+      list_page_ids: String::new(),
+      get_page: String::new(),
+      find_page_id: String::new(),
+      list_all_revisions: String::new(),
+      list_page_revisions: String::new(),
+      fingerprint: String::new(),
+      external_blob: String::new(),
+    };
+
+    queries.list_pages = queries.page_query("");
+    queries.list_pages_since = queries.page_query(&queries.since_cond());
+    queries.list_page_ids = format!(
+      "SELECT {} AS page_id FROM {} WHERE page.page_is_redirect = 0",
+      dialect.int("page.page_id"),
+      queries.table("page"),
+    );
+    queries.get_page =
+      queries.page_query(&format!("AND page.page_id = {}", dialect.param(1)));
+    queries.find_page_id = format!(
+      "SELECT {} AS page_id FROM {} \
+       WHERE page.page_namespace = {} AND page.page_title = {}",
+      dialect.int("page.page_id"),
+      queries.table("page"),
+      dialect.param(1),
+      dialect.param(2),
+    );
+    queries.list_all_revisions = queries.list_revisions(None);
+    queries.list_page_revisions =
+      queries.list_revisions(Some(REVISION_BATCH_SIZE));
+    queries.fingerprint = queries.fingerprint_query();
+    // the `blobs` table of an external store in the wiki's database
+    queries.external_blob = format!(
+      "SELECT {} AS blob_text FROM {} WHERE blobs.blob_id = {}",
+      dialect.bytes("blobs.blob_text"),
+      queries.table("blobs"),
+      dialect.param(1),
+    );
+    queries
+  }
+
+  // all revisions, or the revisions of `page_count` pages bound as
+  // parameters
+  fn list_revisions(&self, page_count: Option<usize>) -> String {
+    let cond = match page_count {
+      None => String::new(),
+      Some(n) => {
+        let params: Vec<String> =
+          (1..=n).map(|i| self.dialect.param(i)).collect();
+        format!("AND revision.rev_page IN ({})", params.join(", "))
+      }
+    };
+    self.revision_query(&cond)
+  }
+
+  // the table under its unprefixed name
+  fn table(&self, name: &str) -> String {
+    self.table_as(name, name)
+  }
+
+  fn table_as(&self, name: &str, alias: &str) -> String {
+    let name = match (self.dialect, name) {
+      // "text" is a type name on Postgres
+      (Dialect::Postgres, "text") => "pagecontent",
+      _ => name,
+    };
+    format!("{}{} AS {}", self.prefix, name, alias)
+  }
+
+  fn title(&self, column: &str) -> String {
+    self.dialect.text(&format!("replace({column}, '_', ' ')"))
+  }
+
+  fn page_query(&self, cond: &str) -> String {
+    let d = self.dialect;
+    format!(
+      "SELECT \
+         {id} AS id, \
+         {title} AS title, \
+         {updated} AS updated, \
+         {created} AS created, \
+         COALESCE({creator}, '') AS creator, \
+         COALESCE({last_editor}, '') AS last_editor, \
+         (SELECT {revision_count} \
+          FROM {counted} \
+          WHERE counted.rev_page = page.page_id) AS revision_count, \
+         page.page_namespace AS namespace, \
+         (SELECT COALESCE({categories}, '') \
+          FROM {categorylinks} \
+          WHERE categorylinks.cl_from = page.page_id) AS categories, \
+         {address} AS content_address, \
+         {old_text} AS old_text, \
+         {old_flags} AS old_flags, \
+         (SELECT COALESCE({aliases}, '') \
+          FROM {redirect} \
+          JOIN {source} ON source.page_id = redirect.rd_from \
+          WHERE redirect.rd_namespace = page.page_namespace \
+            AND redirect.rd_title = page.page_title \
+            AND COALESCE(redirect.rd_interwiki, '') = '') AS aliases \
+       FROM {page} \
+       LEFT JOIN {first_rev} ON first_rev.rev_id = ( \
+         SELECT earliest.rev_id FROM {earliest} \
+         WHERE earliest.rev_page = page.page_id \
+         ORDER BY earliest.rev_timestamp, earliest.rev_id LIMIT 1) \
+       LEFT JOIN {creator_table} ON first_rev.rev_actor = creator.actor_id \
+       LEFT JOIN {latest_rev} ON page.page_latest = latest_rev.rev_id \
+       LEFT JOIN {last_editor_table} \
+         ON latest_rev.rev_actor = last_editor.actor_id \
+       LEFT JOIN {slots} ON page.page_latest = slots.slot_revision_id \
+       LEFT JOIN {content} ON slots.slot_content_id = content.content_id \
+       LEFT JOIN {text_table} ON {text_id} = text.old_id \
+       WHERE page.page_is_redirect = 0 {cond}",
+      id = d.int("page.page_id"),
+      title = self.title("page.page_title"),
+      updated = d.timestamp("page.page_touched"),
+      created =
+        d.timestamp("COALESCE(first_rev.rev_timestamp, page.page_touched)"),
+      creator = d.text("creator.actor_name"),
+      last_editor = d.text("last_editor.actor_name"),
+      revision_count = d.int("COUNT(*)"),
+      counted = self.table_as("revision", "counted"),
+      first_rev = self.table_as("revision", "first_rev"),
+      earliest = self.table_as("revision", "earliest"),
+      creator_table = self.table_as("actor", "creator"),
+      latest_rev = self.table_as("revision", "latest_rev"),
+      last_editor_table = self.table_as("actor", "last_editor"),
+      categories = d.group_concat(&d.text("categorylinks.cl_to")),
+      aliases = d.group_concat(&self.title("source.page_title")),
+      address = d.text("content.content_address"),
+      old_text = d.bytes("text.old_text"),
+      old_flags = d.text("text.old_flags"),
+      categorylinks = self.table("categorylinks"),
+      redirect = self.table("redirect"),
+      source = self.table_as("page", "source"),
+      page = self.table("page"),
+      slots = self.table("slots"),
+      content = self.table("content"),
+      text_table = self.table("text"),
+      text_id = d.text_id("content.content_address"),
+    )
+  }
+
+  // pages whose latest revision or touch time moved past the
+  // fingerprint, or which got new or updated redirects
+  fn since_cond(&self) -> String {
+    let d = self.dialect;
+    format!(
+      "AND (page.page_latest > {p1} OR {touched} >= {p2} \
+         OR EXISTS ( \
+           SELECT 1 FROM {redirect} \
+           JOIN {source} ON source.page_id = redirect.rd_from \
+           WHERE redirect.rd_namespace = page.page_namespace \
+             AND redirect.rd_title = page.page_title \
+             AND (source.page_latest > {p3} \
+               OR {source_touched} >= {p4})))",
+      touched = d.timestamp("page.page_touched"),
+      source_touched = d.timestamp("source.page_touched"),
+      redirect = self.table("redirect"),
+      source = self.table_as("page", "source"),
+      p1 = d.param(1),
+      p2 = d.param(2),
+      p3 = d.param(3),
+      p4 = d.param(4),
+    )
+  }
+
+  fn revision_query(&self, cond: &str) -> String {
+    let d = self.dialect;
+    format!(
+      "SELECT \
+         {id} AS id, \
+         {page_id} AS page_id, \
+         {title} AS title, \
+         page.page_namespace AS namespace, \
+         {timestamp} AS timestamp, \
+         COALESCE({actor}, '') AS actor, \
+         {address} AS content_address, \
+         {old_text} AS old_text, \
+         {old_flags} AS old_flags \
+       FROM {revision} \
+       JOIN {page} ON revision.rev_page = page.page_id \
+       LEFT JOIN {actor_table} ON revision.rev_actor = actor.actor_id \
+       LEFT JOIN {slots} ON revision.rev_id = slots.slot_revision_id \
+       LEFT JOIN {content} ON slots.slot_content_id = content.content_id \
+       LEFT JOIN {text_table} ON {text_id} = text.old_id \
+       WHERE page.page_is_redirect = 0 {cond} \
+       ORDER BY revision.rev_page, revision.rev_timestamp, revision.rev_id",
+      id = d.int("revision.rev_id"),
+      page_id = d.int("revision.rev_page"),
+      title = self.title("page.page_title"),
+      timestamp = d.timestamp("revision.rev_timestamp"),
+      actor = d.text("actor.actor_name"),
+      address = d.text("content.content_address"),
+      old_text = d.bytes("text.old_text"),
+      old_flags = d.text("text.old_flags"),
+      revision = self.table("revision"),
+      page = self.table("page"),
+      actor_table = self.table("actor"),
+      slots = self.table("slots"),
+      content = self.table("content"),
+      text_table = self.table("text"),
+      text_id = d.text_id("content.content_address"),
+    )
+  }
+
+  fn fingerprint_query(&self) -> String {
+    let d = self.dialect;
+    let latest_id = |table: &str, column: &str| match d {
+      // Reading the primary key sequence values is O(1), c.f.
+      // https://stackoverflow.com/a/2217015
+      Dialect::Sqlite => format!(
+        "(SELECT COALESCE(MAX(seq), 0) FROM sqlite_sequence \
+          WHERE name = '{}{}')",
+        self.prefix, table
+      ),
+      // the other databases look up the maximum in the primary key
+      // index, which is almost as cheap
+      _ => format!(
+        "(SELECT {} FROM {})",
+        d.int(&format!("COALESCE(MAX({column}), 0)")),
+        self.table(table)
+      ),
+    };
+
+    format!(
+      "SELECT \
+         {revision} AS revision, \
+         {archive} AS archive, \
+         {logging} AS logging, \
+         (SELECT {page_count} FROM {page}) AS page_count, \
+         (SELECT COALESCE({touched}, '19700101000000') \
+          FROM {page}) AS touched, \
+         (SELECT {category_count} FROM {categorylinks}) AS category_count, \
+         (SELECT COALESCE({category_touched}, '') \
+          FROM {categorylinks}) AS category_touched",
+      revision = latest_id("revision", "rev_id"),
+      archive = latest_id("archive", "ar_id"),
+      logging = latest_id("logging", "log_id"),
+      page_count = d.int("COUNT(*)"),
+      touched = d.timestamp("MAX(page.page_touched)"),
+      category_count = d.int("COUNT(*)"),
+      category_touched = d.timestamp("MAX(categorylinks.cl_timestamp)"),
+      page = self.table("page"),
+      categorylinks = self.table("categorylinks"),
+    )
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Dialect, Queries};
+
+  #[test]
+  fn test_dialect_from_url() {
+    let dialect = |url| Dialect::from_url(url).unwrap();
+    assert_eq!(dialect("sqlite:///srv/wiki/wiki.sqlite"), Dialect::Sqlite);
+    assert_eq!(dialect("mariadb://wiki@localhost/wikidb"), Dialect::MySql);
+    assert_eq!(dialect("postgresql://localhost/wikidb"), Dialect::Postgres);
+    assert!(Dialect::from_url("/srv/wiki/wiki.sqlite").is_err());
+  }
+
+  #[test]
+  fn test_table_prefix() {
+    for dialect in [Dialect::Sqlite, Dialect::MySql, Dialect::Postgres] {
+      let queries = Queries::new(dialect, "mw_");
+      let sqls = [
+        &queries.list_pages,
+        &queries.list_pages_since,
+        &queries.list_page_ids,
+        &queries.get_page,
+        &queries.find_page_id,
+        &queries.fingerprint,
+        &queries.external_blob,
+        &queries.list_revisions(Some(3)),
+      ];
+
+      for sql in sqls {
+        for keyword in ["FROM ", "JOIN "] {
+          for (i, _) in sql.match_indices(keyword) {
+            let table = &sql[i + keyword.len()..];
+            assert!(
+              table.starts_with("mw_") || table.starts_with("sqlite_sequence"),
+              "unprefixed table in {dialect:?}: {table}"
+            );
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_dialect_specifics() {
+    let postgres = Queries::new(Dialect::Postgres, "");
+    assert!(postgres.list_pages.contains("string_agg("));
+    assert!(postgres.list_pages.contains("pagecontent AS text"));
+    assert!(postgres.list_pages.contains("convert_to(text.old_text"));
+    assert!(postgres.list_pages_since.contains("$4"));
+    assert!(postgres.list_revisions(Some(2)).contains("IN ($1, $2)"));
+    assert!(!postgres.fingerprint.contains("sqlite_sequence"));
+
+    let mysql = Queries::new(Dialect::MySql, "");
+    assert!(mysql.list_pages.contains("SEPARATOR '<|||>'"));
+    assert!(mysql.list_pages.contains("CAST(text.old_flags AS CHAR)"));
+    assert!(mysql.list_revisions(Some(2)).contains("IN (?, ?)"));
+
+    let sqlite = Queries::new(Dialect::Sqlite, "");
+    assert!(sqlite.fingerprint.contains("sqlite_sequence"));
+    assert!(sqlite.list_pages.contains("ltrim(content.content_address"));
+  }
+}
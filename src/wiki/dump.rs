@@ -0,0 +1,552 @@
+// Reads the pages of a wiki from a MediaWiki XML dump, as exported by
+// `Special:Export` or `dumpBackup.php`, for when there is no database
+// to read from.
+
+use std::{
+  collections::HashMap,
+  fs::File,
+  io::{BufRead, BufReader},
+  mem,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use futures_util::{stream, stream::BoxStream, StreamExt, TryStreamExt};
+use quick_xml::events::{BytesStart, Event};
+use regex::Regex;
+use tokio::sync::mpsc;
+
+use crate::{
+  namespace::{Namespaces, CATEGORY},
+  page::{Categories, Page, Revision, TitleDate, WikiTimestamp},
+  util::{self, in_background, Error, Result},
+};
+
+use super::{prepare_page, prepare_revision, StreamedPage};
+
+// pages and revisions read ahead of the indexing
+const READ_AHEAD: usize = 64;
+
+/// A MediaWiki XML dump, optionally compressed with bzip2 (`.bz2`) or
+/// gzip (`.gz`).
+#[derive(Clone)]
+pub struct Dump {
+  path: PathBuf,
+  wiki_base: String,
+  namespaces: Namespaces,
+  history: bool,
+  threads: usize,
+  /// titles of the redirects to each full title, see `Dump::scan`
+  aliases: Arc<HashMap<String, Vec<String>>>,
+}
+
+#[derive(Default)]
+struct DumpPage {
+  id: i64,
+  title: String,
+  namespace: i32,
+  redirect: Option<String>,
+  revisions: Vec<DumpRevision>,
+  /// timestamp and contributor of the first revision in the dump
+  created: String,
+  creator: String,
+  revision_count: i64,
+}
+
+#[derive(Default)]
+struct DumpRevision {
+  id: i64,
+  timestamp: String,
+  contributor: String,
+  text: String,
+}
+
+/// A page, or a past revision in history mode, as read from a dump.
+enum DumpEntry {
+  Page(Page),
+  Revision(Revision),
+}
+
+// The dump is read twice, as a redirect can come before or after the
+// page it redirects to: the first pass only collects the redirects,
+// the second one passes the pages with their aliases to `emit`.
+struct DumpReader<'a> {
+  history: bool,
+  /// including the namespace names from `<siteinfo>`
+  namespaces: Namespaces,
+  category_link: Option<Regex>,
+  /// titles of the redirects to a full title
+  aliases: HashMap<String, Vec<String>>,
+  /// receives the pages and revisions, None in the first pass
+  emit: Option<&'a mut dyn FnMut(DumpEntry) -> Result<()>>,
+}
+
+impl Dump {
+  pub fn new(path: impl Into<PathBuf>, wiki_base: impl Into<String>) -> Self {
+    Self {
+      path: path.into(),
+      wiki_base: wiki_base.into(),
+      namespaces: Namespaces::default(),
+      history: false,
+      threads: util::default_threads(),
+      aliases: Arc::default(),
+    }
+  }
+
+  // namespaces known in addition to those listed in the dump
+  pub fn with_namespaces(mut self, namespaces: Namespaces) -> Self {
+    self.namespaces = namespaces;
+    self
+  }
+
+  // also read past revisions for indexing
+  pub fn with_history(mut self, history: bool) -> Self {
+    self.history = history;
+    self
+  }
+
+  pub fn with_threads(mut self, threads: usize) -> Self {
+    self.threads = threads.max(1);
+    self
+  }
+
+  // Read the namespaces and the redirects of the dump, on the blocking
+  // thread pool. Needed before streaming its pages.
+  pub async fn scan(self) -> Result<Self> {
+    let scanned = in_background(Some(self), |mut dump| {
+      let mut reader = DumpReader::new(dump.namespaces.clone(), false, None);
+      reader.read(open(&dump.path)?)?;
+      dump.namespaces = reader.namespaces;
+      dump.aliases = Arc::new(reader.aliases);
+      Ok(Some(dump))
+    });
+    Ok(scanned.await?.expect("a dump was given"))
+  }
+
+  // Stream all pages, and all their past revisions in history mode,
+  // as the dump is read on the blocking thread pool, a few pages ahead
+  // of the consumers. The dump must be scanned first, and both streams
+  // consumed at the same time. Redirects are not listed but added as
+  // aliases of their target. Empty pages are yielded as skipped, and
+  // empty revisions as None, like those of `Wiki::stream_pages`.
+  #[allow(clippy::type_complexity)]
+  pub fn stream(
+    &self,
+  ) -> (
+    BoxStream<'static, Result<StreamedPage>>,
+    BoxStream<'static, Result<Option<Revision>>>,
+  ) {
+    let (page_sender, pages) = mpsc::channel(READ_AHEAD);
+    let (revision_sender, revisions) = mpsc::channel(READ_AHEAD);
+
+    let dump = self.clone();
+    tokio::task::spawn_blocking(move || {
+      let mut emit = |entry: DumpEntry| {
+        let sent = match entry {
+          DumpEntry::Page(page) => page_sender.blocking_send(Ok(page)).is_ok(),
+          DumpEntry::Revision(revision) => {
+            revision_sender.blocking_send(Ok(revision)).is_ok()
+          }
+        };
+        if sent {
+          Ok(())
+        } else {
+          Err(Error::Generic("the dump is no longer read".into()))
+        }
+      };
+      let aliases = dump.aliases.as_ref().clone();
+      let mut reader =
+        DumpReader::new(dump.namespaces.clone(), dump.history, Some(&mut emit));
+      reader.aliases = aliases;
+      let read = open(&dump.path).and_then(|source| reader.read(source));
+      if let Err(e) = read {
+        let _ = page_sender.blocking_send(Err(e));
+      }
+    });
+
+    let pages = self.prepare(receive(pages), prepare_dump_page);
+    let revisions = self.prepare(receive(revisions), prepare_revision);
+    (pages, revisions)
+  }
+
+  // textify the entries on the blocking thread pool
+  fn prepare<T: Send + 'static, U: Send + 'static>(
+    &self,
+    entries: BoxStream<'static, Result<T>>,
+    prepare: fn(T, &str, &Namespaces) -> U,
+  ) -> BoxStream<'static, Result<U>> {
+    let wiki_base = self.wiki_base.clone();
+    let namespaces = self.namespaces.clone();
+    entries
+      .map_ok(move |entry| {
+        let (wiki_base, namespaces) = (wiki_base.clone(), namespaces.clone());
+        let prepared = in_background(Some(entry), move |entry| {
+          Ok(Some(prepare(entry, &wiki_base, &namespaces)))
+        });
+        async move { Ok(prepared.await?.expect("an entry was given")) }
+      })
+      .try_buffered(self.threads)
+      .boxed()
+  }
+}
+
+// like `prepare_page`, keeping the id of an empty page
+fn prepare_dump_page(
+  page: Page,
+  wiki_base: &str,
+  namespaces: &Namespaces,
+) -> StreamedPage {
+  let id = page.id;
+  StreamedPage::prepared(id, prepare_page(page, wiki_base, namespaces))
+}
+
+fn receive<T: Send + 'static>(
+  receiver: mpsc::Receiver<Result<T>>,
+) -> BoxStream<'static, Result<T>> {
+  stream::unfold(receiver, |mut receiver| async move {
+    let item = receiver.recv().await?;
+    Some((item, receiver))
+  })
+  .boxed()
+}
+
+fn open(path: &Path) -> Result<Box<dyn BufRead>> {
+  let file = File::open(path)?;
+  let source: Box<dyn BufRead> =
+    match path.extension().and_then(|ext| ext.to_str()) {
+      Some("bz2") => {
+        Box::new(BufReader::new(bzip2::read::MultiBzDecoder::new(file)))
+      }
+      Some("gz") => {
+        Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file)))
+      }
+      _ => Box::new(BufReader::new(file)),
+    };
+  Ok(source)
+}
+
+impl<'a> DumpReader<'a> {
+  fn new(
+    namespaces: Namespaces,
+    history: bool,
+    emit: Option<&'a mut dyn FnMut(DumpEntry) -> Result<()>>,
+  ) -> Self {
+    Self {
+      history,
+      namespaces,
+      category_link: None,
+      aliases: HashMap::new(),
+      emit,
+    }
+  }
+
+  fn read(&mut self, source: impl BufRead) -> Result<()> {
+    let mut reader = quick_xml::Reader::from_reader(source);
+    let mut buf = vec![];
+    // names of the open elements
+    let mut path: Vec<Vec<u8>> = vec![];
+    // text of the innermost element
+    let mut text = String::new();
+
+    let mut namespace_key = 0;
+    let mut page = DumpPage::default();
+    let mut revision = DumpRevision::default();
+
+    loop {
+      match reader.read_event_into(&mut buf)? {
+        Event::Start(e) => {
+          let name = e.local_name().as_ref().to_vec();
+          if name == b"namespace" {
+            namespace_key = attribute(&e, "key")?
+              .and_then(|key| key.parse().ok())
+              .unwrap_or_default();
+          }
+          path.push(name);
+          text.clear();
+        }
+        Event::Empty(e) if e.local_name().as_ref() == b"redirect" => {
+          page.redirect = attribute(&e, "title")?;
+        }
+        Event::Text(e) => text.push_str(&e.unescape()?),
+        Event::CData(e) => text.push_str(&String::from_utf8_lossy(&e)),
+        Event::End(_) => {
+          let name = path.pop().unwrap_or_default();
+          let parent = path.last().map(|parent| parent.as_slice());
+          let value = mem::take(&mut text);
+
+          match (parent, name.as_slice()) {
+            (Some(b"namespaces"), b"namespace") => {
+              self.namespaces.define(namespace_key, &value, &[]);
+            }
+            (Some(b"page"), b"title") => page.title = value,
+            (Some(b"page"), b"ns") => page.namespace = parse_int(&value)?,
+            (Some(b"page"), b"id") => page.id = parse_int(&value)?,
+            (Some(b"revision"), b"id") => revision.id = parse_int(&value)?,
+            (Some(b"revision"), b"timestamp") => revision.timestamp = value,
+            (Some(b"revision"), b"text") => revision.text = value,
+            (Some(b"contributor"), b"username" | b"ip") => {
+              revision.contributor = value;
+            }
+            (Some(b"page"), b"revision") => {
+              if page.revision_count == 0 {
+                page.created = revision.timestamp.clone();
+                page.creator = revision.contributor.clone();
+              }
+              page.revision_count += 1;
+              // without history, only the latest revision is needed
+              if !self.history {
+                page.revisions.clear();
+              }
+              page.revisions.push(mem::take(&mut revision));
+            }
+            (_, b"page") => self.add_page(mem::take(&mut page))?,
+            _ => {}
+          }
+        }
+        Event::Eof => break,
+        _ => {}
+      }
+      buf.clear();
+    }
+
+    Ok(())
+  }
+
+  fn add_page(&mut self, mut dump_page: DumpPage) -> Result<()> {
+    let title = self.strip_namespace(&dump_page);
+
+    // the first pass only collects the redirects, the second one
+    // skips them
+    let first_pass = self.emit.is_none();
+    if let Some(target) = dump_page.redirect {
+      if first_pass {
+        self.aliases.entry(target).or_default().push(title);
+      }
+      return Ok(());
+    }
+    if first_pass {
+      return Ok(());
+    }
+
+    // dumps list the revisions of a page from oldest to newest
+    let Some(latest) = dump_page.revisions.pop() else {
+      return Ok(());
+    };
+
+    let mut prev: Option<Revision> = None;
+    for dump_revision in dump_page.revisions.into_iter().chain([latest]) {
+      let revision = Revision {
+        id: dump_revision.id,
+        page_id: dump_page.id,
+        title: title.clone(),
+        text: dump_revision.text,
+        title_date: TitleDate::try_from(title.clone())?,
+        namespace: self.namespaces.get(dump_page.namespace),
+        timestamp: parse_timestamp(&dump_revision.timestamp)?,
+        valid_until: None,
+        actor: dump_revision.contributor,
+        url: String::new(),
+      };
+
+      if let Some(mut prev) = prev.take() {
+        prev.valid_until = Some(revision.timestamp.clone());
+        self.add_revision(prev)?;
+      }
+      prev = Some(revision);
+    }
+
+    // the latest revision is both the page and its last revision
+    let Some(latest) = prev else { return Ok(()) };
+    let page = Page {
+      id: latest.page_id,
+      title: latest.title.clone(),
+      text: latest.text.clone(),
+      title_date: latest.title_date.clone(),
+      updated: latest.timestamp.clone(),
+      created: parse_timestamp(&dump_page.created)?,
+      creator: dump_page.creator,
+      last_editor: latest.actor.clone(),
+      revision_count: dump_page.revision_count,
+      namespace: latest.namespace.clone(),
+      url: String::new(),
+      categories: Categories::Split(self.categories(&latest.text)),
+      aliases: Categories::Split(
+        self.aliases.remove(&dump_page.title).unwrap_or_default(),
+      ),
+      sections: vec![],
+    };
+    self.add_revision(latest)?;
+    self.emit(DumpEntry::Page(page))
+  }
+
+  fn add_revision(&mut self, revision: Revision) -> Result<()> {
+    if !self.history {
+      return Ok(());
+    }
+    self.emit(DumpEntry::Revision(revision))
+  }
+
+  fn emit(&mut self, entry: DumpEntry) -> Result<()> {
+    match &mut self.emit {
+      Some(emit) => emit(entry),
+      None => Ok(()),
+    }
+  }
+
+  // dumps use the full title, e.g. "Talk:Main Page"
+  fn strip_namespace(&self, page: &DumpPage) -> String {
+    let namespace = self.namespaces.get(page.namespace);
+    let prefix = Some(namespace.name.as_str())
+      .filter(|name| !name.is_empty())
+      .and_then(|name| page.title.strip_prefix(name))
+      .and_then(|rest| rest.strip_prefix(':'));
+
+    prefix.unwrap_or(&page.title).to_string()
+  }
+
+  // Dumps don't include the category links table, so categories are
+  // taken from the category links in the wikitext. Categories added
+  // by templates are missed.
+  fn categories(&mut self, wikitext: &str) -> Vec<String> {
+    let namespaces = &self.namespaces;
+    let category_link = self.category_link.get_or_insert_with(|| {
+      let category = namespaces.get(CATEGORY);
+      let names: Vec<String> = [&category.name]
+        .into_iter()
+        .chain(&category.aliases)
+        .map(|name| regex::escape(name))
+        .collect();
+      let pattern =
+        format!(r"(?i)\[\[\s*(?:{})\s*:\s*([^\]|\n]+)", names.join("|"));
+      Regex::new(&pattern).expect("invalid category link regex")
+    });
+
+    let mut categories: Vec<String> = vec![];
+    for captures in category_link.captures_iter(wikitext) {
+      let category = normalize_title(&captures[1]);
+      if !category.is_empty() && !categories.contains(&category) {
+        categories.push(category);
+      }
+    }
+    categories
+  }
+}
+
+fn attribute(e: &BytesStart, name: &str) -> Result<Option<String>> {
+  let Some(attr) = e.try_get_attribute(name)? else {
+    return Ok(None);
+  };
+  Ok(Some(attr.unescape_value()?.into_owned()))
+}
+
+fn parse_int<T: std::str::FromStr>(value: &str) -> Result<T> {
+  value
+    .trim()
+    .parse()
+    .map_err(|_| Error::Generic(format!("invalid number in dump: {value}")))
+}
+
+// dumps use ISO 8601 timestamps, e.g. "2023-01-02T10:00:00Z"
+fn parse_timestamp(value: &str) -> Result<WikiTimestamp> {
+  let date_time = chrono::DateTime::parse_from_rfc3339(value)
+    .map_err(|_| Error::InvalidDate(value.to_string()))?;
+  Ok(WikiTimestamp(date_time.into()))
+}
+
+// category names as stored in the database, e.g. "Programming_languages"
+fn normalize_title(title: &str) -> String {
+  let title = title.trim().replace(' ', "_");
+  let mut chars = title.chars();
+  match chars.next() {
+    Some(first) => first.to_uppercase().chain(chars).collect(),
+    None => title,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::{io::Write, path::Path};
+
+  use futures_util::TryStreamExt;
+
+  use crate::page::{Page, Revision};
+
+  use super::{Dump, StreamedPage};
+
+  const FIXTURE: &str = include_str!("fixtures/dump.xml");
+
+  async fn read(dump: Dump) -> (Vec<Page>, Vec<Revision>) {
+    let (pages, revisions) = dump.scan().await.unwrap().stream();
+    let pages = pages.try_filter_map(|page| async {
+      match page {
+        StreamedPage::Page(page) => Ok(Some(page)),
+        StreamedPage::Skipped(_) => Ok(None),
+      }
+    });
+    let revisions = revisions.try_filter_map(|rev| async { Ok(rev) });
+    futures_util::try_join!(pages.try_collect(), revisions.try_collect())
+      .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_read_dump() {
+    let path =
+      Path::new(env!("CARGO_MANIFEST_DIR")).join("src/wiki/fixtures/dump.xml");
+    let dump = Dump::new(path, "https://wiki.example/").with_history(true);
+    let (pages, revisions) = read(dump).await;
+
+    let titles: Vec<_> = pages.iter().map(|p| p.title.as_str()).collect();
+    assert_eq!(titles, ["Jan 2, 2023", "Rust", "About"]);
+
+    let diary = &pages[0];
+    assert_eq!(diary.text, "Went hiking in the mountains.");
+    assert_eq!(diary.updated.to_wiki_format(), "20230102100000");
+    assert_eq!(diary.created.to_wiki_format(), "20230102080000");
+    assert_eq!(diary.creator, "Alice");
+    assert_eq!(diary.last_editor, "192.0.2.1");
+    assert_eq!(diary.revision_count, 2);
+    assert_eq!(diary.categories.iter().collect::<Vec<_>>(), ["Diary"]);
+    assert!(diary.title_date.0.is_some());
+
+    let rust = &pages[1];
+    let categories: Vec<_> = rust.categories.iter().collect();
+    assert_eq!(categories, ["Programming_languages", "Software"]);
+    assert_eq!(rust.aliases.iter().collect::<Vec<_>>(), ["Rust (language)"]);
+    assert_eq!(rust.sections[1].anchor, "History");
+
+    let about = &pages[2];
+    assert_eq!(about.url, "https://wiki.example/Notes:About");
+    assert_eq!(about.namespace.aliases, ["Project"]);
+
+    let diary_revisions: Vec<_> =
+      revisions.iter().filter(|rev| rev.page_id == 1).collect();
+    assert_eq!(diary_revisions.len(), 2);
+    assert_eq!(diary_revisions[0].actor, "Alice");
+    assert_eq!(diary_revisions[0].text, "Went hiking.");
+    assert_eq!(
+      diary_revisions[0].valid_until,
+      Some(diary_revisions[1].timestamp.clone())
+    );
+  }
+
+  #[tokio::test]
+  async fn test_read_compressed_dump() {
+    use flate2::{write::GzEncoder, Compression};
+
+    let file = format!("wiki-search-dump-{}.xml.gz", std::process::id());
+    let path = std::env::temp_dir().join(file);
+    let mut encoder = GzEncoder::new(
+      std::fs::File::create(&path).unwrap(),
+      Compression::fast(),
+    );
+    encoder.write_all(FIXTURE.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+
+    let (pages, revisions) =
+      read(Dump::new(&path, "https://wiki.example/")).await;
+    assert_eq!(pages.len(), 3);
+    assert!(revisions.is_empty());
+
+    let _ = std::fs::remove_file(&path);
+  }
+}
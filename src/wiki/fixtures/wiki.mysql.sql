@@ -0,0 +1,138 @@
+-- The tiny MediaWiki database of wiki.sql, as laid out by MediaWiki's
+-- MySQL schema: binary strings for titles, names and timestamps, and
+-- unsigned ids. `/*_*/` marks the table prefix.
+
+DROP TABLE IF EXISTS /*_*/page, /*_*/revision, /*_*/slots, /*_*/content,
+  /*_*/text, /*_*/actor, /*_*/categorylinks, /*_*/redirect, /*_*/blobs,
+  /*_*/archive, /*_*/logging;
+
+CREATE TABLE /*_*/page (
+  page_id INT UNSIGNED AUTO_INCREMENT NOT NULL,
+  page_namespace INT NOT NULL,
+  page_title VARBINARY(255) NOT NULL,
+  page_is_redirect TINYINT UNSIGNED DEFAULT 0 NOT NULL,
+  page_touched BINARY(14) NOT NULL,
+  page_latest INT UNSIGNED NOT NULL,
+  PRIMARY KEY(page_id)
+);
+
+CREATE TABLE /*_*/revision (
+  rev_id BIGINT UNSIGNED AUTO_INCREMENT NOT NULL,
+  rev_page INT UNSIGNED NOT NULL,
+  rev_actor BIGINT UNSIGNED DEFAULT 0 NOT NULL,
+  rev_timestamp BINARY(14) NOT NULL,
+  PRIMARY KEY(rev_id)
+);
+
+CREATE TABLE /*_*/slots (
+  slot_revision_id BIGINT UNSIGNED NOT NULL,
+  slot_role_id SMALLINT UNSIGNED NOT NULL,
+  slot_content_id BIGINT UNSIGNED NOT NULL,
+  PRIMARY KEY(slot_revision_id, slot_role_id)
+);
+
+CREATE TABLE /*_*/content (
+  content_id BIGINT UNSIGNED AUTO_INCREMENT NOT NULL,
+  content_address VARBINARY(255) NOT NULL,
+  PRIMARY KEY(content_id)
+);
+
+CREATE TABLE /*_*/text (
+  old_id INT UNSIGNED AUTO_INCREMENT NOT NULL,
+  old_text MEDIUMBLOB NOT NULL,
+  old_flags TINYBLOB NOT NULL,
+  PRIMARY KEY(old_id)
+);
+
+CREATE TABLE /*_*/actor (
+  actor_id BIGINT UNSIGNED AUTO_INCREMENT NOT NULL,
+  actor_name VARBINARY(255) NOT NULL,
+  PRIMARY KEY(actor_id)
+);
+
+CREATE TABLE /*_*/categorylinks (
+  cl_from INT UNSIGNED DEFAULT 0 NOT NULL,
+  cl_to VARBINARY(255) DEFAULT '' NOT NULL,
+  cl_timestamp TIMESTAMP NOT NULL,
+  PRIMARY KEY(cl_from, cl_to)
+);
+
+CREATE TABLE /*_*/redirect (
+  rd_from INT UNSIGNED DEFAULT 0 NOT NULL,
+  rd_namespace INT DEFAULT 0 NOT NULL,
+  rd_title VARBINARY(255) DEFAULT '' NOT NULL,
+  rd_interwiki VARCHAR(32) DEFAULT NULL,
+  PRIMARY KEY(rd_from)
+);
+
+-- an external store in the wiki's own database
+CREATE TABLE /*_*/blobs (
+  blob_id INT UNSIGNED AUTO_INCREMENT NOT NULL,
+  blob_text LONGBLOB NOT NULL,
+  PRIMARY KEY(blob_id)
+);
+
+CREATE TABLE /*_*/archive (
+  ar_id INT UNSIGNED AUTO_INCREMENT NOT NULL,
+  ar_title VARBINARY(255) DEFAULT '' NOT NULL,
+  PRIMARY KEY(ar_id)
+);
+
+CREATE TABLE /*_*/logging (
+  log_id INT UNSIGNED AUTO_INCREMENT NOT NULL,
+  log_type VARBINARY(32) DEFAULT '' NOT NULL,
+  PRIMARY KEY(log_id)
+);
+
+INSERT INTO /*_*/actor VALUES (1, 'Alice'), (2, 'Bob');
+
+INSERT INTO /*_*/page VALUES
+  (1, 0, 'Jan_2,_2023', 0, '20230102100000', 2),
+  (2, 0, 'Rust', 0, '20230105090000', 3),
+  (3, 0, 'Rust_(language)', 1, '20230105090000', 4),
+  (4, 4, 'Compressed', 0, '20230103090000', 5),
+  (5, 100, 'External', 0, '20230103090000', 6),
+  (6, 0, 'Legacy_external', 0, '20230103090000', 7),
+  (7, 0, 'Serialized', 0, '20230103090000', 8);
+
+INSERT INTO /*_*/revision VALUES
+  (1, 1, 1, '20230102080000'),
+  (2, 1, 2, '20230102100000'),
+  (3, 2, 1, '20230105090000'),
+  (4, 3, 1, '20230105090000'),
+  (5, 4, 1, '20230103090000'),
+  (6, 5, 1, '20230103090000'),
+  (7, 6, 1, '20230103090000'),
+  (8, 7, 1, '20230103090000');
+
+INSERT INTO /*_*/text VALUES
+  (1, 'Went hiking.', 'utf-8'),
+  (2, 'Went hiking in the [[Alps|mountains]].', 'utf-8'),
+  (3, '''''Rust'''' is a language.
+== History ==
+Started in 2006.', 'utf-8'),
+  (4, '#REDIRECT [[Rust]]', 'utf-8'),
+  (5, X'73CECF2D284A2D2E4E4D512849AD2851484CCA2F2D51883ABCA7283339430F00',
+    'utf-8,gzip'),
+  (6, 'DB://cluster1/2', 'utf-8,external'),
+  (7, 'O:19:"historyblobcurstub":1:{s:6:"mCurId";i:1;}', 'object');
+
+INSERT INTO /*_*/blobs VALUES
+  (1, X'0B49AD2851C8CC5348CC5300B2528BF21273148A4BF28B52F500'),
+  (2, 'Text in an old external store.');
+
+INSERT INTO /*_*/content VALUES
+  (1, 'tt:1'), (2, 'tt:2'), (3, 'tt:3'), (4, 'tt:4'), (5, 'tt:5'),
+  (6, 'es:DB://cluster1/1?flags=utf-8,gzip'), (7, 'tt:6'), (8, 'tt:7');
+
+INSERT INTO /*_*/slots VALUES
+  (1, 1, 1), (2, 1, 2), (3, 1, 3), (4, 1, 4),
+  (5, 1, 5), (6, 1, 6), (7, 1, 7), (8, 1, 8);
+
+INSERT INTO /*_*/categorylinks VALUES
+  (1, 'Diary', '2023-01-02 10:00:00'),
+  (2, 'Programming', '2023-01-05 09:00:00');
+
+INSERT INTO /*_*/redirect VALUES (3, 0, 'Rust', '');
+
+INSERT INTO /*_*/logging VALUES (1, 'create');
@@ -0,0 +1,130 @@
+-- The tiny MediaWiki database of wiki.sql, as laid out by MediaWiki's
+-- Postgres schema: text columns for binary strings, timestamps with
+-- time zone, and `pagecontent` for the text table. Postgres can't
+-- store compressed text in a text column, so the compressed rows are
+-- stored plain. `/*_*/` marks the table prefix.
+
+DROP TABLE IF EXISTS /*_*/page, /*_*/revision, /*_*/slots, /*_*/content,
+  /*_*/pagecontent, /*_*/actor, /*_*/categorylinks, /*_*/redirect,
+  /*_*/blobs, /*_*/archive, /*_*/logging;
+
+CREATE TABLE /*_*/page (
+  page_id SERIAL NOT NULL PRIMARY KEY,
+  page_namespace INT NOT NULL,
+  page_title TEXT NOT NULL,
+  page_is_redirect SMALLINT DEFAULT 0 NOT NULL,
+  page_touched TIMESTAMPTZ NOT NULL,
+  page_latest INT NOT NULL
+);
+
+CREATE TABLE /*_*/revision (
+  rev_id SERIAL NOT NULL PRIMARY KEY,
+  rev_page INT NOT NULL,
+  rev_actor BIGINT DEFAULT 0 NOT NULL,
+  rev_timestamp TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE /*_*/slots (
+  slot_revision_id BIGINT NOT NULL,
+  slot_role_id SMALLINT NOT NULL,
+  slot_content_id BIGINT NOT NULL,
+  PRIMARY KEY(slot_revision_id, slot_role_id)
+);
+
+CREATE TABLE /*_*/content (
+  content_id BIGSERIAL NOT NULL PRIMARY KEY,
+  content_address TEXT NOT NULL
+);
+
+CREATE TABLE /*_*/pagecontent (
+  old_id SERIAL NOT NULL PRIMARY KEY,
+  old_text TEXT NOT NULL,
+  old_flags TEXT NOT NULL
+);
+
+CREATE TABLE /*_*/actor (
+  actor_id BIGSERIAL NOT NULL PRIMARY KEY,
+  actor_name TEXT NOT NULL
+);
+
+CREATE TABLE /*_*/categorylinks (
+  cl_from INT DEFAULT 0 NOT NULL,
+  cl_to TEXT DEFAULT '' NOT NULL,
+  cl_timestamp TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY(cl_from, cl_to)
+);
+
+CREATE TABLE /*_*/redirect (
+  rd_from INT DEFAULT 0 NOT NULL PRIMARY KEY,
+  rd_namespace INT DEFAULT 0 NOT NULL,
+  rd_title TEXT DEFAULT '' NOT NULL,
+  rd_interwiki VARCHAR(32) DEFAULT NULL
+);
+
+-- an external store in the wiki's own database
+CREATE TABLE /*_*/blobs (
+  blob_id SERIAL NOT NULL PRIMARY KEY,
+  blob_text TEXT NOT NULL
+);
+
+CREATE TABLE /*_*/archive (
+  ar_id SERIAL NOT NULL PRIMARY KEY,
+  ar_title TEXT DEFAULT '' NOT NULL
+);
+
+CREATE TABLE /*_*/logging (
+  log_id SERIAL NOT NULL PRIMARY KEY,
+  log_type TEXT DEFAULT '' NOT NULL
+);
+
+INSERT INTO /*_*/actor VALUES (1, 'Alice'), (2, 'Bob');
+
+INSERT INTO /*_*/page VALUES
+  (1, 0, 'Jan_2,_2023', 0, '2023-01-02 10:00:00+00', 2),
+  (2, 0, 'Rust', 0, '2023-01-05 09:00:00+00', 3),
+  (3, 0, 'Rust_(language)', 1, '2023-01-05 09:00:00+00', 4),
+  (4, 4, 'Compressed', 0, '2023-01-03 09:00:00+00', 5),
+  (5, 100, 'External', 0, '2023-01-03 09:00:00+00', 6),
+  (6, 0, 'Legacy_external', 0, '2023-01-03 09:00:00+00', 7),
+  (7, 0, 'Serialized', 0, '2023-01-03 09:00:00+00', 8);
+
+INSERT INTO /*_*/revision VALUES
+  (1, 1, 1, '2023-01-02 08:00:00+00'),
+  (2, 1, 2, '2023-01-02 10:00:00+00'),
+  (3, 2, 1, '2023-01-05 09:00:00+00'),
+  (4, 3, 1, '2023-01-05 09:00:00+00'),
+  (5, 4, 1, '2023-01-03 09:00:00+00'),
+  (6, 5, 1, '2023-01-03 09:00:00+00'),
+  (7, 6, 1, '2023-01-03 09:00:00+00'),
+  (8, 7, 1, '2023-01-03 09:00:00+00');
+
+INSERT INTO /*_*/pagecontent VALUES
+  (1, 'Went hiking.', 'utf-8'),
+  (2, 'Went hiking in the [[Alps|mountains]].', 'utf-8'),
+  (3, '''''Rust'''' is a language.
+== History ==
+Started in 2006.', 'utf-8'),
+  (4, '#REDIRECT [[Rust]]', 'utf-8'),
+  (5, 'Compressed text about Zürich.', 'utf-8'),
+  (6, 'DB://cluster1/2', 'utf-8,external'),
+  (7, 'O:19:"historyblobcurstub":1:{s:6:"mCurId";i:1;}', 'object');
+
+INSERT INTO /*_*/blobs VALUES
+  (1, 'Text in an external store.'),
+  (2, 'Text in an old external store.');
+
+INSERT INTO /*_*/content VALUES
+  (1, 'tt:1'), (2, 'tt:2'), (3, 'tt:3'), (4, 'tt:4'), (5, 'tt:5'),
+  (6, 'es:DB://cluster1/1?flags=utf-8'), (7, 'tt:6'), (8, 'tt:7');
+
+INSERT INTO /*_*/slots VALUES
+  (1, 1, 1), (2, 1, 2), (3, 1, 3), (4, 1, 4),
+  (5, 1, 5), (6, 1, 6), (7, 1, 7), (8, 1, 8);
+
+INSERT INTO /*_*/categorylinks VALUES
+  (1, 'Diary', '2023-01-02 10:00:00+00'),
+  (2, 'Programming', '2023-01-05 09:00:00+00');
+
+INSERT INTO /*_*/redirect VALUES (3, 0, 'Rust', '');
+
+INSERT INTO /*_*/logging VALUES (1, 'create');
@@ -0,0 +1,125 @@
+-- A tiny MediaWiki database: the tables read by wiki-search, as laid
+-- out by MediaWiki's SQLite schema. `/*_*/` marks the table prefix.
+
+CREATE TABLE /*_*/page (
+  page_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  page_namespace INTEGER NOT NULL,
+  page_title BLOB NOT NULL,
+  page_is_redirect SMALLINT DEFAULT 0 NOT NULL,
+  page_touched BLOB NOT NULL,
+  page_latest INTEGER UNSIGNED NOT NULL
+);
+
+CREATE TABLE /*_*/revision (
+  rev_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  rev_page INTEGER UNSIGNED NOT NULL,
+  rev_actor BIGINT UNSIGNED DEFAULT 0 NOT NULL,
+  rev_timestamp BLOB NOT NULL
+);
+
+CREATE TABLE /*_*/slots (
+  slot_revision_id BIGINT UNSIGNED NOT NULL,
+  slot_role_id SMALLINT UNSIGNED NOT NULL,
+  slot_content_id BIGINT UNSIGNED NOT NULL,
+  PRIMARY KEY(slot_revision_id, slot_role_id)
+);
+
+CREATE TABLE /*_*/content (
+  content_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  content_address BLOB NOT NULL
+);
+
+CREATE TABLE /*_*/text (
+  old_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  old_text BLOB NOT NULL,
+  old_flags BLOB NOT NULL
+);
+
+CREATE TABLE /*_*/actor (
+  actor_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  actor_name BLOB NOT NULL
+);
+
+CREATE TABLE /*_*/categorylinks (
+  cl_from INTEGER UNSIGNED DEFAULT 0 NOT NULL,
+  cl_to BLOB DEFAULT '' NOT NULL,
+  cl_timestamp DATETIME NOT NULL,
+  PRIMARY KEY(cl_from, cl_to)
+);
+
+CREATE TABLE /*_*/redirect (
+  rd_from INTEGER UNSIGNED DEFAULT 0 NOT NULL,
+  rd_namespace INTEGER DEFAULT 0 NOT NULL,
+  rd_title BLOB DEFAULT '' NOT NULL,
+  rd_interwiki VARCHAR(32) DEFAULT NULL,
+  PRIMARY KEY(rd_from)
+);
+
+-- an external store in the wiki's own database
+CREATE TABLE /*_*/blobs (
+  blob_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  blob_text BLOB NOT NULL
+);
+
+CREATE TABLE /*_*/archive (
+  ar_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  ar_title BLOB DEFAULT '' NOT NULL
+);
+
+CREATE TABLE /*_*/logging (
+  log_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  log_type BLOB DEFAULT '' NOT NULL
+);
+
+INSERT INTO /*_*/actor VALUES (1, 'Alice'), (2, 'Bob');
+
+INSERT INTO /*_*/page VALUES
+  (1, 0, 'Jan_2,_2023', 0, '20230102100000', 2),
+  (2, 0, 'Rust', 0, '20230105090000', 3),
+  (3, 0, 'Rust_(language)', 1, '20230105090000', 4),
+  (4, 4, 'Compressed', 0, '20230103090000', 5),
+  (5, 100, 'External', 0, '20230103090000', 6),
+  (6, 0, 'Legacy_external', 0, '20230103090000', 7),
+  (7, 0, 'Serialized', 0, '20230103090000', 8);
+
+INSERT INTO /*_*/revision VALUES
+  (1, 1, 1, '20230102080000'),
+  (2, 1, 2, '20230102100000'),
+  (3, 2, 1, '20230105090000'),
+  (4, 3, 1, '20230105090000'),
+  (5, 4, 1, '20230103090000'),
+  (6, 5, 1, '20230103090000'),
+  (7, 6, 1, '20230103090000'),
+  (8, 7, 1, '20230103090000');
+
+INSERT INTO /*_*/text VALUES
+  (1, 'Went hiking.', 'utf-8'),
+  (2, 'Went hiking in the [[Alps|mountains]].', 'utf-8'),
+  (3, '''''Rust'''' is a language.
+== History ==
+Started in 2006.', 'utf-8'),
+  (4, '#REDIRECT [[Rust]]', 'utf-8'),
+  (5, X'73CECF2D284A2D2E4E4D512849AD2851484CCA2F2D51883ABCA7283339430F00',
+    'utf-8,gzip'),
+  (6, 'DB://cluster1/2', 'utf-8,external'),
+  (7, 'O:19:"historyblobcurstub":1:{s:6:"mCurId";i:1;}', 'object');
+
+INSERT INTO /*_*/blobs VALUES
+  (1, X'0B49AD2851C8CC5348CC5300B2528BF21273148A4BF28B52F500'),
+  (2, 'Text in an old external store.');
+
+INSERT INTO /*_*/content VALUES
+  (1, 'tt:1'), (2, 'tt:2'), (3, 'tt:3'), (4, 'tt:4'), (5, 'tt:5'),
+  (6, 'es:DB://cluster1/1?flags=utf-8,gzip'), (7, 'tt:6'), (8, 'tt:7');
+
+INSERT INTO /*_*/slots VALUES
+  (1, 1, 1), (2, 1, 2), (3, 1, 3), (4, 1, 4),
+  (5, 1, 5), (6, 1, 6), (7, 1, 7), (8, 1, 8);
+
+INSERT INTO /*_*/categorylinks VALUES
+  (1, 'Diary', '2023-01-02 10:00:00'),
+  (2, 'Programming', '2023-01-05 09:00:00');
+
+INSERT INTO /*_*/redirect VALUES (3, 0, 'Rust', '');
+
+INSERT INTO /*_*/logging VALUES (1, 'create');
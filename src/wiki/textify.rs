@@ -1,22 +1,531 @@
-#![allow(dead_code)]
+// Convert wikitext into plain text suitable for indexing.
+//
+// This is not a complete MediaWiki parser, it only aims to strip the
+// markup while keeping the visible text and the paragraph structure:
+//
+// - comments, templates, magic words and references are removed
+// - links are replaced by their labels
+// - bold/italic quotes and html tags are removed
+// - headings, list items and table rows are put on their own lines
+// - paragraphs are separated by an empty line
+// - the content of <nowiki>, <pre> and the like is kept verbatim
+
+use std::collections::HashSet;
 
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
 
+use crate::page::Section;
+
 pub fn textify(source: &str) -> String {
-  // Do all the following conversions in one pass.
-  //
-  // 1. convert newline to spaces
-  // 2. remove html tags (but retain contents)
-  // 3. merge consecutive spaces/newlines
+  join_sections(&sections(source))
+}
+
+// Put the sections back together into one text, with each heading on
+// its own line.
+pub fn join_sections(sections: &[Section]) -> String {
+  let mut out = String::new();
+
+  for section in sections {
+    let heading = section.headings.last();
+    if heading.is_none() && section.text.is_empty() {
+      continue;
+    }
+
+    if !out.is_empty() {
+      out.push_str("\n\n");
+    }
+    if let Some(heading) = heading {
+      out.push_str(heading);
+      if !section.text.is_empty() {
+        out.push('\n');
+      }
+    }
+    out.push_str(&section.text);
+  }
+
+  out
+}
+
+// Convert the wikitext into plain text split at the headings. The
+// first (lead) section has no heading and is always present.
+pub fn sections(source: &str) -> Vec<Section> {
+  let source = strip_comments(source);
+  let source = protect_verbatim(&source);
+  let source = strip_refs(&source);
+  let source = strip_templates(&source);
+
+  let mut builder = SectionBuilder::default();
+  let mut output = Output::default();
+  let mut table_depth = 0;
+
+  for line in source.lines() {
+    let trimmed = line.trim();
+
+    if trimmed.starts_with("{|") {
+      table_depth += 1;
+      output.paragraph_break();
+      continue;
+    }
+
+    if table_depth > 0 {
+      if trimmed.starts_with("|}") {
+        table_depth -= 1;
+        output.paragraph_break();
+      } else {
+        table_line(&mut output, trimmed);
+      }
+      continue;
+    }
+
+    if trimmed.is_empty() || is_horizontal_rule(trimmed) {
+      output.paragraph_break();
+    } else if let Some((level, heading)) = heading(trimmed) {
+      let text = std::mem::take(&mut output).finish();
+      builder.start_section(level, &inline(heading), text);
+    } else if let Some(item) = list_item(line) {
+      output.line(&inline(item));
+    } else if line.starts_with(' ') {
+      // preformatted text
+      output.line(&inline(trimmed));
+    } else {
+      output.text(&inline(trimmed));
+    }
+  }
+
+  builder.finish(output.finish())
+}
+
+#[derive(Default)]
+struct SectionBuilder {
+  sections: Vec<Section>,
+  // (level, heading) of the headings enclosing the current section
+  headings: Vec<(usize, String)>,
+  anchors: HashSet<String>,
+}
+
+impl SectionBuilder {
+  fn start_section(&mut self, level: usize, heading: &str, text: String) {
+    self.push_section(text);
+
+    let heading = collapse_spaces(&decode_entities(&restore_verbatim(heading)));
+    while matches!(self.headings.last(), Some((l, _)) if *l >= level) {
+      self.headings.pop();
+    }
+    self.headings.push((level, heading));
+  }
+
+  fn push_section(&mut self, text: String) {
+    let headings: Vec<String> =
+      self.headings.iter().map(|(_, h)| h.clone()).collect();
+    let anchor = match headings.last() {
+      Some(heading) => self.unique_anchor(heading),
+      None => String::new(),
+    };
+    let text = decode_entities(&restore_verbatim(&text));
+
+    self.sections.push(Section {
+      headings,
+      anchor,
+      text,
+    });
+  }
+
+  // same as MediaWiki, repeated headings get a numeric suffix
+  fn unique_anchor(&mut self, heading: &str) -> String {
+    let base = heading.replace(' ', "_");
+    let mut anchor = base.clone();
+    let mut n = 1;
+    while self.anchors.contains(&anchor) {
+      n += 1;
+      anchor = format!("{}_{}", base, n);
+    }
+    self.anchors.insert(anchor.clone());
+    anchor
+  }
+
+  fn finish(mut self, text: String) -> Vec<Section> {
+    self.push_section(text);
+    self.sections
+  }
+}
+
+// Accumulates the plain text output while keeping track of the
+// paragraph boundaries.
+#[derive(Default)]
+struct Output {
+  text: String,
+  in_paragraph: bool,
+}
+
+impl Output {
+  // continue the current paragraph
+  fn text(&mut self, s: &str) {
+    if s.trim().is_empty() {
+      return;
+    }
+
+    if self.in_paragraph {
+      self.text.push(' ');
+    }
+    self.text.push_str(s);
+    self.in_paragraph = true;
+  }
+
+  // put the text on its own line
+  fn line(&mut self, s: &str) {
+    if s.trim().is_empty() {
+      return;
+    }
+
+    self.end_line();
+    self.text.push_str(s);
+    self.text.push('\n');
+    self.in_paragraph = false;
+  }
+
+  fn paragraph_break(&mut self) {
+    self.end_line();
+    if !self.text.is_empty() && !self.text.ends_with("\n\n") {
+      self.text.push('\n');
+    }
+  }
+
+  fn end_line(&mut self) {
+    if self.in_paragraph {
+      self.text.push('\n');
+      self.in_paragraph = false;
+    }
+  }
 
+  fn finish(self) -> String {
+    let mut out = String::with_capacity(self.text.len());
+    let mut blank_lines = 0;
+
+    for line in self.text.lines() {
+      let line = collapse_spaces(line);
+      if line.is_empty() {
+        blank_lines += 1;
+        continue;
+      }
+
+      if !out.is_empty() {
+        out.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+      }
+      out.push_str(&line);
+      blank_lines = 0;
+    }
+
+    out
+  }
+}
+
+fn strip_comments(source: &str) -> String {
+  static REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)<!--.*?(-->|$)").unwrap());
+
+  REGEX.replace_all(source, "").into_owned()
+}
+
+fn strip_refs(source: &str) -> String {
+  static REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+      r"(?xsi)
+      <ref\b[^>]*/>|
+      <ref\b[^>]*>.*?</ref\s*>|
+      <references\b[^>]*/>|
+      <references\b[^>]*>.*?</references\s*>
+    ",
+    )
+    .unwrap()
+  });
+
+  REGEX.replace_all(source, "").into_owned()
+}
+
+// Remove templates, parser functions and template parameters. They
+// may be nested, so it's done by keeping track of the open braces.
+fn strip_templates(source: &str) -> String {
+  let mut out = String::with_capacity(source.len());
+  // number of braces of each open template/parameter
+  let mut stack: Vec<usize> = vec![];
+  let mut rest = source;
+
+  while let Some(c) = rest.chars().next() {
+    if rest.starts_with("{{{") {
+      stack.push(3);
+      rest = &rest[3..];
+    } else if rest.starts_with("{{") {
+      stack.push(2);
+      rest = &rest[2..];
+    } else if !stack.is_empty() && rest.starts_with("}}") {
+      let n = if rest.starts_with("}}}") && stack.last() == Some(&3) {
+        3
+      } else {
+        2
+      };
+      stack.pop();
+      rest = &rest[n..];
+    } else {
+      if stack.is_empty() {
+        out.push(c);
+      }
+      rest = &rest[c.len_utf8()..];
+    }
+  }
+
+  out
+}
+
+// Characters with special meaning in wikitext, which are replaced by
+// private use characters inside verbatim blocks so they are left
+// alone by the conversion.
+const MARKUP_CHARS: &str = "[]{}'<>|=*#:;!-_~";
+const PRIVATE_USE_START: u32 = 0xF0000;
+
+fn protect_verbatim(source: &str) -> String {
+  static REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+      r"(?xsi)
+      <nowiki\s*/>|
+      <nowiki\b[^>]*>(?P<nowiki>.*?)</nowiki\s*>|
+      <(?P<tag>pre|syntaxhighlight|source|math|code)\b[^>]*>
+        (?P<content>.*?)
+      </(?P<close>pre|syntaxhighlight|source|math|code)\s*>
+    ",
+    )
+    .unwrap()
+  });
+
+  REGEX
+    .replace_all(source, |caps: &Captures| {
+      if let Some(nowiki) = caps.name("nowiki") {
+        escape_markup(nowiki.as_str())
+      } else if let Some(content) = caps.name("content") {
+        // keep the block apart from the surrounding paragraph
+        let block = matches!(
+          caps["tag"].to_ascii_lowercase().as_str(),
+          "pre" | "syntaxhighlight" | "source"
+        );
+        let content = escape_markup(content.as_str());
+        if block {
+          format!("\n\n{}\n\n", content.trim_matches('\n'))
+        } else {
+          content
+        }
+      } else {
+        String::new()
+      }
+    })
+    .into_owned()
+}
+
+fn escape_markup(s: &str) -> String {
+  s.chars()
+    .map(|c| match MARKUP_CHARS.chars().position(|m| m == c) {
+      Some(i) => char::from_u32(PRIVATE_USE_START + i as u32).unwrap(),
+      None => c,
+    })
+    .collect()
+}
+
+fn restore_verbatim(s: &str) -> String {
+  let markup: Vec<char> = MARKUP_CHARS.chars().collect();
+
+  s.chars()
+    .map(|c| {
+      let i = (c as u32).wrapping_sub(PRIVATE_USE_START) as usize;
+      markup.get(i).copied().unwrap_or(c)
+    })
+    .collect()
+}
+
+fn is_horizontal_rule(line: &str) -> bool {
+  line.len() >= 4 && line.chars().all(|c| c == '-')
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+  let open = line.len() - line.trim_start_matches('=').len();
+  let close = line.len() - line.trim_end_matches('=').len();
+  let level = open.min(close).min(6);
+  if level == 0 || line.len() <= 2 * level {
+    return None;
+  }
+
+  // unbalanced equal signs belong to the heading text
+  let heading = line[level..line.len() - level].trim();
+  (!heading.is_empty()).then_some((level, heading))
+}
+
+fn list_item(line: &str) -> Option<&str> {
+  let item = line.trim_start_matches(['*', '#', ':', ';']);
+  if item.len() == line.len() {
+    return None;
+  }
+
+  Some(item.trim())
+}
+
+fn table_line(output: &mut Output, line: &str) {
+  if line.starts_with("|-") {
+    output.end_line();
+  } else if let Some(caption) = line.strip_prefix("|+") {
+    output.line(&inline(table_cell(caption)));
+  } else if let Some(cells) = line.strip_prefix('!') {
+    // header cells may be separated by either !! or ||
+    let cells = cells.replace("!!", "||");
+    table_cells(output, &cells);
+  } else if let Some(cells) = line.strip_prefix('|') {
+    table_cells(output, cells);
+  } else {
+    // continuation of the previous cell
+    output.text(&inline(line));
+  }
+}
+
+fn table_cells(output: &mut Output, cells: &str) {
+  for cell in split_outside_links(cells, "||") {
+    output.text(&inline(table_cell(cell)));
+  }
+}
+
+// drop the attributes of the cell, i.e. the part before a single |
+fn table_cell(cell: &str) -> &str {
+  match split_outside_links(cell, "|").as_slice() {
+    [_attrs, content, ..] => content,
+    _ => cell,
+  }
+}
+
+// split by the separator, but not inside [[links]]
+fn split_outside_links<'a>(s: &'a str, sep: &str) -> Vec<&'a str> {
+  let mut parts = vec![];
+  let mut depth = 0;
+  let mut start = 0;
+  let mut i = 0;
+
+  while i < s.len() {
+    let rest = &s[i..];
+    if rest.starts_with("[[") {
+      depth += 1;
+      i += 2;
+    } else if rest.starts_with("]]") && depth > 0 {
+      depth -= 1;
+      i += 2;
+    } else if depth == 0 && rest.starts_with(sep) {
+      parts.push(&s[start..i]);
+      i += sep.len();
+      start = i;
+    } else {
+      i += rest.chars().next().unwrap().len_utf8();
+    }
+  }
+
+  parts.push(&s[start..]);
+  parts
+}
+
+fn inline(source: &str) -> String {
+  let text = internal_links(source);
+  let text = external_links(&text);
+  let text = strip_formatting(&text);
+  strip_tags(&text)
+}
+
+fn internal_links(source: &str) -> String {
+  // only match innermost links, so the links nested inside an image
+  // caption are replaced first
+  static REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\[\[([^\[\]]*)\]\]").unwrap());
+
+  let mut text = source.to_string();
+  loop {
+    let replaced = REGEX
+      .replace_all(&text, |caps: &Captures| link_label(&caps[1]).to_string());
+    if replaced == text {
+      return text;
+    }
+    text = replaced.into_owned();
+  }
+}
+
+fn link_label(link: &str) -> &str {
+  let mut parts = link.split('|');
+  let target = parts.next().unwrap_or_default().trim();
+  let params: Vec<&str> = parts.map(|s| s.trim()).collect();
+
+  let namespace = match target.split_once(':') {
+    Some((ns, _)) => ns.trim().to_ascii_lowercase(),
+    None => String::new(),
+  };
+
+  match namespace.as_str() {
+    // category links are not rendered
+    "category" => "",
+    // images show their caption, which is the last unnamed parameter
+    "file" | "image" => params
+      .iter()
+      .rev()
+      .find(|p| !is_image_option(p))
+      .copied()
+      .unwrap_or_default(),
+    _ => match params.last() {
+      // pipe trick: [[Foo (bar)|]] shows "Foo"
+      Some(&"") => pipe_trick(target.trim_start_matches(':')),
+      Some(label) => label,
+      None => target.trim_start_matches(':'),
+    },
+  }
+}
+
+fn pipe_trick(target: &str) -> &str {
+  let target = match target.split_once(':') {
+    Some((_ns, title)) => title,
+    None => target,
+  };
+  match target.split_once(" (") {
+    Some((title, _)) => title,
+    None => target.split(", ").next().unwrap_or(target),
+  }
+}
+
+fn is_image_option(param: &str) -> bool {
+  const OPTIONS: [&str; 16] = [
+    "thumb",
+    "thumbnail",
+    "frame",
+    "framed",
+    "frameless",
+    "border",
+    "left",
+    "right",
+    "center",
+    "none",
+    "upright",
+    "baseline",
+    "middle",
+    "top",
+    "bottom",
+    "sub",
+  ];
+
+  static SIZE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\d*(x\d+)?px$").unwrap());
+
+  OPTIONS.contains(&param)
+    || SIZE.is_match(param)
+    || param.contains('=')
+    || param.is_empty()
+}
+
+fn external_links(source: &str) -> String {
   static REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
       r"(?x)
-      (?P<newline>\n+)|
-      (?P<space>\s\s+)|
-      (?P<open_tag><[^>]+>)|
-      (?P<close_tag></[^>]+>)
+      \[
+        (?:(?:[a-z]+:)?//|mailto:)[^\s\]]+
+        (?:\s+(?P<label>[^\]]*))?
+      \]
     ",
     )
     .unwrap()
@@ -24,19 +533,116 @@ pub fn textify(source: &str) -> String {
 
   REGEX
     .replace_all(source, |caps: &Captures| {
-      if let Some(newline) = caps.name("newline") {
-        " ".repeat(newline.as_str().len())
-      } else if let Some(space) = caps.name("space") {
-        " ".repeat(space.as_str().len())
-      } else if let Some(_open_tag) = caps.name("open_tag") {
-        " ".to_string()
-      } else if let Some(_close_tag) = caps.name("close_tag") {
-        " ".to_string()
+      caps
+        .name("label")
+        .map(|m| m.as_str().trim().to_string())
+        .unwrap_or_default()
+    })
+    .into_owned()
+}
+
+fn strip_formatting(source: &str) -> String {
+  // bold/italic quotes and behavior switches like __TOC__
+  static REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"'{2,}|__[A-Z]+__").unwrap());
+
+  REGEX.replace_all(source, "").into_owned()
+}
+
+fn strip_tags(source: &str) -> String {
+  // remove html tags but retain their contents. Inline tags are
+  // removed without a trace, other tags leave a space behind.
+  static REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+      r"(?xi)
+      </?(?P<inline>
+        b|i|u|s|em|strong|small|big|sup|sub|span|font|abbr|del|ins|
+        tt|kbd|var|q|mark|strike|code|math
+      )\b[^>]*>|
+      </?[a-z][a-z0-9]*\b[^>]*>
+    ",
+    )
+    .unwrap()
+  });
+
+  REGEX
+    .replace_all(source, |caps: &Captures| {
+      if caps.name("inline").is_some() {
+        ""
       } else {
-        unreachable!()
+        " "
       }
     })
-    .to_string()
+    .into_owned()
+}
+
+fn decode_entities(source: &str) -> String {
+  static REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+      r"&(?:#(?P<dec>\d+)|#[xX](?P<hex>[0-9a-fA-F]+)|(?P<name>[a-zA-Z]+));",
+    )
+    .unwrap()
+  });
+
+  REGEX
+    .replace_all(source, |caps: &Captures| {
+      let c = if let Some(dec) = caps.name("dec") {
+        dec.as_str().parse().ok().and_then(char::from_u32)
+      } else if let Some(hex) = caps.name("hex") {
+        u32::from_str_radix(hex.as_str(), 16)
+          .ok()
+          .and_then(char::from_u32)
+      } else {
+        named_entity(&caps["name"])
+      };
+
+      match c {
+        Some(c) => c.to_string(),
+        None => caps[0].to_string(),
+      }
+    })
+    .into_owned()
+}
+
+fn named_entity(name: &str) -> Option<char> {
+  let c = match name {
+    "amp" => '&',
+    "lt" => '<',
+    "gt" => '>',
+    "quot" => '"',
+    "apos" => '\'',
+    "nbsp" => ' ',
+    "ndash" => '–',
+    "mdash" => '—',
+    "hellip" => '…',
+    "middot" => '·',
+    "times" => '×',
+    "copy" => '©',
+    "reg" => '®',
+    "deg" => '°',
+    "laquo" => '«',
+    "raquo" => '»',
+    "lsquo" => '‘',
+    "rsquo" => '’',
+    "ldquo" => '“',
+    "rdquo" => '”',
+    "larr" => '←',
+    "rarr" => '→',
+    _ => return None,
+  };
+
+  Some(c)
+}
+
+fn collapse_spaces(line: &str) -> String {
+  let mut out = String::with_capacity(line.len());
+  for word in line.split_whitespace() {
+    if !out.is_empty() {
+      out.push(' ');
+    }
+    out.push_str(word);
+  }
+  out
 }
 
 #[cfg(test)]
@@ -45,17 +651,71 @@ mod test {
 
   #[test]
   fn test_textify() {
-    // assert_textify("a&quot;b", "a\"b");
-    assert_textify("{{a|b=c|d=e}}", "{{a|b=c|d=e}}");
-    assert_textify("{{a|b|c}}", "{{a|b|c}}");
-    assert_textify("''hello''", "''hello''");
-    assert_textify("'''hello'''", "'''hello'''");
-    assert_textify("'''''hello'''''", "'''''hello'''''");
-    assert_textify("'''''hello'''''", "'''''hello'''''");
-
-    // bug: there is no newline between b and c
-    // assert_textify("= a =\nb\n\n= c =\nd", "= a =\nb= c =\nd");
-    assert_textify("= a =\nb\n\n= c =\nd", "= a = b  = c = d");
+    assert_textify("a&quot;b", "a\"b");
+    assert_textify("{{a|b=c|d=e}}", "");
+    assert_textify("a{{b|{{c}}|d}}e", "ae");
+    assert_textify("{{{1|default}}}x", "x");
+    assert_textify("''hello''", "hello");
+    assert_textify("'''hello'''", "hello");
+    assert_textify("'''''hello'''''", "hello");
+    assert_textify("__TOC__\nhello", "hello");
+    assert_textify("[[a]] [[a|b]] [[a]]s", "a b as");
+    assert_textify("[[Category:a]]b", "b");
+    assert_textify("[[File:a.png|thumb|200px|a [[b]]]]", "a b");
+    assert_textify("[https://example.com label] [https://x.y]", "label");
+    assert_textify("a<ref>b</ref>c<ref name=\"d\" />", "ac");
+    assert_textify("<nowiki>''a''</nowiki>", "''a''");
+    assert_textify("a<!-- b -->c", "ac");
+    assert_textify("* a\n* b\n# c", "a\nb\nc");
+    assert_textify("= a =\nb\n\n= c =\nd", "a\nb\n\nc\nd");
+    assert_textify("a\nb\n\nc", "a b\n\nc");
+  }
+
+  #[test]
+  fn test_textify_fixtures() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/src/wiki/textify/fixtures");
+
+    let mut count = 0;
+    for entry in std::fs::read_dir(dir).unwrap() {
+      let path = entry.unwrap().path();
+      if path.extension() != Some("wiki".as_ref()) {
+        continue;
+      }
+
+      let source = std::fs::read_to_string(&path).unwrap();
+      let expected =
+        std::fs::read_to_string(path.with_extension("txt")).unwrap();
+      assert_eq!(
+        textify(&source),
+        expected.trim_end(),
+        "fixture {}",
+        path.display()
+      );
+      count += 1;
+    }
+
+    assert!(count > 0, "no fixtures found in {}", dir);
+  }
+
+  #[test]
+  fn test_sections() {
+    let source = "a\n== b ==\nc\n=== d ===\ne\n== b ==\n\n== f g ==\nh";
+    let sections = sections(source);
+    let summary: Vec<_> = sections
+      .iter()
+      .map(|s| (s.headings.join(" > "), s.anchor.as_str(), s.text.as_str()))
+      .collect();
+
+    assert_eq!(
+      summary,
+      vec![
+        ("".to_string(), "", "a"),
+        ("b".to_string(), "b", "c"),
+        ("b > d".to_string(), "d", "e"),
+        ("b".to_string(), "b_2", ""),
+        ("f g".to_string(), "f_g", "h"),
+      ]
+    );
   }
 
   fn assert_textify(source: &str, expected: &str) {